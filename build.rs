@@ -19,7 +19,13 @@ fn main() {
     )) && !cfg!(feature = "stock-zlib");
 
     if want_ng && target != "wasm32-unknown-unknown" {
-        return build_zlib_ng(&target, true);
+        build_zlib_ng(&target, true);
+        let (version, _) = header_version("src/zlib-ng/zlib.h.in");
+        // zlib-ng's compat mode provides the whole zlib API, whatever
+        // `ZLIB_VERNUM` it claims.
+        emit_version(version, Some(u32::MAX));
+        emit_source("zlib-ng-compat");
+        return;
     }
 
     // All android compilers should come with libz by default, so let's just use
@@ -27,10 +33,14 @@ fn main() {
     // so we can link to it even when cross-compiling.
     if target.contains("android") || target.contains("haiku") || target.ends_with("-ohos") {
         println!("cargo:rustc-link-lib=z");
+        let (version, vernum) = probe_version(&[]);
+        emit_version(version, vernum);
+        emit_source("platform");
         return;
     }
 
     let want_static = should_link_static();
+    let mut pkg_config = None;
     // Don't run pkg-config if we're linking statically (we'll build below) and
     // also don't run pkg-config on FreeBSD/DragonFly. That'll end up printing
     // `-L /usr/lib` which wreaks havoc with linking to an OpenSSL in /usr/local/lib
@@ -58,6 +68,7 @@ fn main() {
                         .collect::<Vec<_>>();
                     println!("cargo:include={}", paths.join(","));
                 }
+                pkg_config = Some((zlib.version, zlib.include_paths));
             }
             Err(e) => {
                 println!(
@@ -68,8 +79,13 @@ fn main() {
         }
     }
 
-    if target.contains("windows") && try_vcpkg() {
-        return;
+    if target.contains("windows") {
+        if let Some(paths) = try_vcpkg() {
+            let (version, vernum) = probe_version(&paths);
+            emit_version(version, vernum);
+            emit_source("vcpkg");
+            return;
+        }
     }
 
     let mut cfg = cc::Build::new();
@@ -91,6 +107,20 @@ fn main() {
     // otherwise continue below to build things.
     if zlib_installed(&mut cfg) {
         println!("cargo:rustc-link-lib=z");
+        let found_by_pkg_config = pkg_config.is_some();
+        let (pc_version, include_paths) = match pkg_config {
+            Some((version, paths)) => (Some(version), paths),
+            None => (None, Vec::new()),
+        };
+        let (version, vernum) = probe_version(&include_paths);
+        // The `.pc` file describes the package rather than the header, so its
+        // version is only used if the header couldn't be read.
+        emit_version(version.or(pc_version), vernum);
+        emit_source(if found_by_pkg_config {
+            "pkg-config"
+        } else {
+            "smoke-test"
+        });
         return;
     }
 
@@ -144,14 +174,8 @@ fn build_zlib(cfg: &mut cc::Build, target: &str) {
     fs::copy("src/zlib/zconf.h", dst.join("include/zconf.h")).unwrap();
 
     fs::create_dir_all(lib.join("pkgconfig")).unwrap();
-    let zlib_h = fs::read_to_string(dst.join("include/zlib.h")).unwrap();
-    let version = zlib_h
-        .lines()
-        .find(|l| l.contains("ZLIB_VERSION"))
-        .unwrap()
-        .split("\"")
-        .nth(1)
-        .unwrap();
+    let (version, vernum) = header_version(dst.join("include/zlib.h"));
+    let version = version.unwrap();
     fs::write(
         lib.join("pkgconfig/zlib.pc"),
         fs::read_to_string("src/zlib/zlib.pc.in")
@@ -159,15 +183,166 @@ fn build_zlib(cfg: &mut cc::Build, target: &str) {
             .replace("@prefix@", dst.to_str().unwrap())
             .replace("@includedir@", "${prefix}/include")
             .replace("@libdir@", "${prefix}/lib")
-            .replace("@VERSION@", version),
+            .replace("@VERSION@", &version),
     )
     .unwrap();
 
+    emit_version(Some(version), vernum);
+    emit_source("bundled-zlib");
+
     println!("cargo:root={}", dst.to_str().unwrap());
     println!("cargo:rustc-link-search=native={}", lib.to_str().unwrap());
     println!("cargo:include={}/include", dst.to_str().unwrap());
 }
 
+/// APIs that were added to zlib after 1.2.3.4, keyed by the `ZLIB_VERNUM` of
+/// the release that introduced them. `src/lib.rs` gates on these cfgs.
+const VERSION_CFGS: &[(u32, &str)] = &[
+    (0x1235, "zlib_1_2_3_5"),
+    (0x1251, "zlib_1_2_5_1"),
+    (0x1252, "zlib_1_2_5_2"),
+    (0x1271, "zlib_1_2_7_1"),
+    (0x1290, "zlib_1_2_9"),
+    (0x12c0, "zlib_1_2_12"),
+];
+
+/// Enables the cfg of every entry in `VERSION_CFGS` that `vernum` is at least
+/// as new as. An unknown version only gets the baseline API.
+///
+/// The list is also passed on to dependents as `DEP_Z_VERSION_CFGS`.
+fn emit_version_cfgs(vernum: Option<u32>) {
+    let vernum = vernum.unwrap_or(0);
+    let enabled = VERSION_CFGS
+        .iter()
+        .filter(|(min, _)| vernum >= *min)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>();
+    for name in &enabled {
+        println!("cargo:rustc-cfg={}", name);
+    }
+    println!("cargo:version_cfgs={}", enabled.join(","));
+}
+
+/// Records the zlib version the build found: as the version cfgs, as the
+/// `ZLIB_VERSION` constant (through `LIBZ_SYS_ZLIB_VERSION`) and as
+/// `DEP_Z_VERSION` for dependents. `vernum` falls back to parsing `version`.
+fn emit_version(version: Option<String>, vernum: Option<u32>) {
+    let vernum = vernum.or_else(|| version.as_deref().and_then(parse_vernum));
+    emit_version_cfgs(vernum);
+    if let Some(version) = version {
+        println!("cargo:rustc-env=LIBZ_SYS_ZLIB_VERSION={}", version);
+        println!("cargo:version={}", version);
+    }
+}
+
+/// Reads `ZLIB_VERSION` and `ZLIB_VERNUM` from a zlib header.
+fn header_version(path: impl AsRef<std::path::Path>) -> (Option<String>, Option<u32>) {
+    let header = fs::read_to_string(path).unwrap_or_default();
+    let version = define_value(&header, "#define ZLIB_VERSION").map(str::to_owned);
+    let vernum = define_value(&header, "#define ZLIB_VERNUM").and_then(parse_hex);
+    (version, vernum)
+}
+
+/// Asks the C compiler which zlib.h it sees, looking in `include_paths` before
+/// its default search path.
+fn probe_version(include_paths: &[PathBuf]) -> (Option<String>, Option<u32>) {
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let probe = out_dir.join("version_probe.c");
+    fs::write(
+        &probe,
+        "#include <zlib.h>\n\
+         libz_sys_version ZLIB_VERSION\n\
+         libz_sys_vernum ZLIB_VERNUM\n",
+    )
+    .unwrap();
+
+    let expanded = match cc::Build::new()
+        .includes(include_paths)
+        .file(&probe)
+        .cargo_metadata(false)
+        .try_expand()
+    {
+        Ok(expanded) => String::from_utf8_lossy(&expanded).into_owned(),
+        Err(e) => {
+            println!("note, could not probe the zlib.h version: {}", e);
+            return (None, None);
+        }
+    };
+
+    // The preprocessor may split an expansion over several lines, with line
+    // markers in between. A macro the header doesn't define stays unexpanded.
+    let tokens = expanded
+        .lines()
+        .filter(|l| !l.trim_start().starts_with('#'))
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>();
+    let value_after = |marker: &str| {
+        tokens
+            .windows(2)
+            .find(|w| w[0] == marker)
+            .map(|w| w[1].trim_matches('"'))
+            .filter(|v| !v.starts_with("ZLIB_"))
+    };
+    let version = value_after("libz_sys_version").map(str::to_owned);
+    let vernum = value_after("libz_sys_vernum").and_then(parse_hex);
+    (version, vernum)
+}
+
+/// Finds the line starting with `prefix` and returns the first token after
+/// it, without quotes.
+fn define_value<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    text.lines().find_map(|line| {
+        let rest = line.trim_start().strip_prefix(prefix)?;
+        if !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        let value = rest.split_whitespace().next()?.trim_matches('"');
+        Some(value).filter(|v| !v.is_empty())
+    })
+}
+
+/// Parses a C hex literal such as `0x12d0` or `0x020303F0L`.
+fn parse_hex(literal: &str) -> Option<u32> {
+    let digits = literal
+        .strip_prefix("0x")
+        .or_else(|| literal.strip_prefix("0X"))?;
+    u32::from_str_radix(digits.trim_end_matches(['L', 'U', 'l', 'u']), 16).ok()
+}
+
+/// Tells the crate which way the library it links to was found, for
+/// `safe::implementation`: `zlib-ng-compat` or `bundled-zlib` when built
+/// here, otherwise `platform` (Android, Haiku and OpenHarmony), `vcpkg`,
+/// `pkg-config` or `smoke-test`.
+fn emit_source(source: &str) {
+    println!("cargo:rustc-env=LIBZ_SYS_SOURCE={}", source);
+}
+
+/// Turns a version string such as `1.2.13` into the `ZLIB_VERNUM` encoding,
+/// one nibble per component. Components past 15 saturate, which keeps the
+/// ordering intact for everything `VERSION_CFGS` cares about.
+fn parse_vernum(version: &str) -> Option<u32> {
+    let mut vernum = 0;
+    let mut parts = version.trim().split('.');
+    for shift in [12, 8, 4, 0] {
+        let part = match parts.next() {
+            Some(part) => part,
+            None if shift < 8 => break,
+            None => return None,
+        };
+        let digits = part
+            .find(|c: char| !c.is_ascii_digit())
+            .map_or(part, |end| &part[..end]);
+        let n = match digits.parse::<u32>() {
+            Ok(n) => n,
+            // Suffixes such as the `zlib-ng` in `1.3.1.zlib-ng`.
+            Err(_) if shift < 8 => break,
+            Err(_) => return None,
+        };
+        vernum |= n.min(15) << shift;
+    }
+    Some(vernum)
+}
+
 #[cfg(any(
     feature = "zlib-ng",
     feature = "zlib-ng-no-cmake-experimental-community-maintained"
@@ -194,7 +369,7 @@ fn build_zlib_ng(_target: &str, _compat: bool) {
     zng::build_zlib_ng(_target, _compat);
 }
 
-fn try_vcpkg() -> bool {
+fn try_vcpkg() -> Option<Vec<PathBuf>> {
     // see if there is a vcpkg tree with zlib installed
     match vcpkg::Config::new()
         .emit_includes(true)
@@ -209,11 +384,11 @@ fn try_vcpkg() -> bool {
                     .collect::<Vec<_>>();
                 println!("cargo:include={}", paths.join(","));
             }
-            true
+            Some(zlib.include_paths)
         }
         Err(e) => {
             println!("note, vcpkg did not find zlib: {}", e);
-            false
+            None
         }
     }
 }
@@ -29,11 +29,11 @@ if [ "${TARGET_TRIPLE%-windows-gnu}" != "$TARGET_TRIPLE" ]; then
     fi
 fi
 
-$CROSS test --target $TARGET_TRIPLE
+$CROSS test --target $TARGET_TRIPLE --features safe,parallel
 $CROSS run --target $TARGET_TRIPLE --manifest-path systest/Cargo.toml
 
 echo '::group::=== zlib-ng build ==='
-$CROSS test --target $TARGET_TRIPLE --no-default-features --features zlib-ng
+$CROSS test --target $TARGET_TRIPLE --no-default-features --features zlib-ng,safe,parallel
 $CROSS run --target $TARGET_TRIPLE --manifest-path systest/Cargo.toml --no-default-features --features zlib-ng
 echo '::endgroup::'
 
@@ -52,7 +52,7 @@ fi
 echo '::group::=== libz-ng-sys build ==='
 mv Cargo-zng.toml Cargo.toml
 mv systest/Cargo-zng.toml systest/Cargo.toml
-$CROSS test --target $TARGET_TRIPLE
+$CROSS test --target $TARGET_TRIPLE --features safe,parallel
 $CROSS run --target $TARGET_TRIPLE --manifest-path systest/Cargo.toml
 echo '::endgroup::'
 
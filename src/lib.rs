@@ -3,6 +3,9 @@
 
 use std::os::raw::{c_char, c_int, c_long, c_uchar, c_uint, c_ulong, c_void};
 
+#[cfg(feature = "safe")]
+pub mod safe;
+
 // Macro for variances between zlib-ng in native mode and either zlib or zlib-ng in zlib compat
 // mode. Note in particular that zlib-ng in compat mode does *not* use the zng case.
 #[cfg(not(zng))]
@@ -38,6 +41,9 @@ pub type uLongf = c_ulong;
 pub type voidp = *mut c_void;
 pub type voidpc = *const c_void;
 pub type voidpf = *mut c_void;
+// The entries of the CRC table. zlib uses a 32-bit unsigned type wherever it can
+// find one; zlib-ng uses uint32_t.
+pub type z_crc_t = u32;
 
 #[cfg(any(zng, feature = "libc"))]
 pub enum gzFile_s {}
@@ -63,6 +69,25 @@ pub type z_off_t = i64;
 #[cfg(all(zng, not(all(windows, not(target_env = "gnu")))))]
 pub type z_off_t = libc::off_t;
 
+// The offset type of the `*64` functions. zlib builds them with 64-bit offsets
+// everywhere it can; zlib-ng in native mode always uses them for `z_off_t`.
+#[cfg(all(
+    not(zng),
+    feature = "libc",
+    not(all(target_family = "wasm", target_os = "unknown"))
+))]
+pub type z_off64_t = i64;
+
+#[cfg(all(
+    not(zng),
+    feature = "libc",
+    all(target_family = "wasm", target_os = "unknown")
+))]
+pub type z_off64_t = c_long;
+
+#[cfg(zng)]
+pub type z_off64_t = z_off_t;
+
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct gz_header {
@@ -154,6 +179,8 @@ extern "C" {
         nice_length: c_int,
         max_chain: c_int,
     ) -> c_int;
+    #[link_name = zng_prefix!(get_crc_table)]
+    pub fn get_crc_table() -> *const z_crc_t;
     #[link_name = zng_prefix!(inflate)]
     pub fn inflate(strm: z_streamp, flush: c_int) -> c_int;
     #[link_name = zng_prefix!(inflateBack)]
@@ -188,29 +215,57 @@ extern "C" {
     ) -> c_int;
     #[link_name = zng_prefix!(inflateSync)]
     pub fn inflateSync(strm: z_streamp) -> c_int;
+    #[link_name = zng_prefix!(inflateUndermine)]
+    pub fn inflateUndermine(strm: z_streamp, subvert: c_int) -> c_int;
     #[link_name = zng_prefix!(zlibCompileFlags)]
     pub fn zlibCompileFlags() -> uLong;
+    #[link_name = zng_prefix!(zError)]
+    pub fn zError(err: c_int) -> *const c_char;
+}
+
+// The above set of functions target 1.2.3.4 (what's present on Ubuntu 12.04).
+// Later additions are gated on the `zlib_*` cfgs that the build script derives
+// from the version of the library it links to.
+#[cfg(any(zng, zlib_1_2_5_1))]
+extern "C" {
+    #[link_name = zng_prefix!(deflatePending)]
+    pub fn deflatePending(strm: z_streamp, pending: *mut c_uint, bits: *mut c_int) -> c_int;
+}
 
-    // The above set of functions currently target 1.2.3.4 (what's present on Ubuntu
-    // 12.04, but there's some other APIs that were added later. Should figure out
-    // how to expose them...
-    //
-    // Added in 1.2.5.1
-    //
-    //     pub fn deflatePending(strm: z_streamp,
-    //                           pending: *mut c_uint,
-    //                           bits: *mut c_int) -> c_int;
-    //
-    // Addedin 1.2.7.1
-    //     pub fn inflateGetDictionary(strm: z_streamp,
-    //                                 dictionary: *mut Bytef,
-    //                                 dictLength: *mut uInt) -> c_int;
-    //
-    // Added in 1.2.3.5
-    //     pub fn gzbuffer(file: gzFile, size: c_uint) -> c_int;
-    //     pub fn gzclose_r(file: gzFile) -> c_int;
-    //     pub fn gzclose_w(file: gzFile) -> c_int;
-    //     pub fn gzoffset(file: gzFile) -> z_off_t;
+#[cfg(any(zng, zlib_1_2_5_2))]
+extern "C" {
+    #[link_name = zng_prefix!(deflateResetKeep)]
+    pub fn deflateResetKeep(strm: z_streamp) -> c_int;
+    #[link_name = zng_prefix!(inflateResetKeep)]
+    pub fn inflateResetKeep(strm: z_streamp) -> c_int;
+}
+
+#[cfg(any(zng, zlib_1_2_7_1))]
+extern "C" {
+    #[link_name = zng_prefix!(inflateGetDictionary)]
+    pub fn inflateGetDictionary(
+        strm: z_streamp,
+        dictionary: *mut Bytef,
+        dictLength: *mut uInt,
+    ) -> c_int;
+}
+
+#[cfg(any(zng, zlib_1_2_9))]
+extern "C" {
+    #[link_name = zng_prefix!(adler32_z)]
+    pub fn adler32_z(adler: z_checksum, buf: *const Bytef, len: usize) -> z_checksum;
+    #[link_name = zng_prefix!(crc32_z)]
+    pub fn crc32_z(crc: z_checksum, buf: *const Bytef, len: usize) -> z_checksum;
+    #[link_name = zng_prefix!(deflateGetDictionary)]
+    pub fn deflateGetDictionary(
+        strm: z_streamp,
+        dictionary: *mut Bytef,
+        dictLength: *mut uInt,
+    ) -> c_int;
+    #[link_name = zng_prefix!(inflateCodesUsed)]
+    pub fn inflateCodesUsed(strm: z_streamp) -> c_ulong;
+    #[link_name = zng_prefix!(inflateValidate)]
+    pub fn inflateValidate(strm: z_streamp, check: c_int) -> c_int;
 }
 
 extern "C" {
@@ -268,6 +323,35 @@ extern "C" {
     pub fn zng_inflateInit2(strm: z_streamp, windowBits: c_int) -> c_int;
 }
 
+// Stream parameters that only zlib-ng's native API exposes. `param` holds one
+// of the `Z_DEFLATE_*` constants.
+#[cfg(zng)]
+pub type zng_deflate_param = c_int;
+
+#[cfg(zng)]
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct zng_deflate_param_value {
+    pub param: zng_deflate_param,
+    pub buf: *mut c_void,
+    pub size: usize,
+    pub status: i32,
+}
+
+#[cfg(zng)]
+extern "C" {
+    pub fn zng_deflateSetParams(
+        strm: z_streamp,
+        params: *mut zng_deflate_param_value,
+        count: usize,
+    ) -> i32;
+    pub fn zng_deflateGetParams(
+        strm: z_streamp,
+        params: *mut zng_deflate_param_value,
+        count: usize,
+    ) -> i32;
+}
+
 // These methods are required to keep BC with original zlib API since zlib-ng 2.1 that changed API
 #[cfg(zng)]
 #[inline(always)]
@@ -394,6 +478,87 @@ extern "C" {
     ) -> c_int;
 }
 
+#[cfg(all(any(zng, feature = "libc"), any(zng, zlib_1_2_3_5)))]
+extern "C" {
+    #[link_name = zng_prefix!(gzbuffer)]
+    pub fn gzbuffer(file: gzFile, size: c_uint) -> c_int;
+    #[link_name = zng_prefix!(gzclose_r)]
+    pub fn gzclose_r(file: gzFile) -> c_int;
+    #[link_name = zng_prefix!(gzclose_w)]
+    pub fn gzclose_w(file: gzFile) -> c_int;
+    #[link_name = zng_prefix!(gzoffset)]
+    pub fn gzoffset(file: gzFile) -> z_off_t;
+}
+
+// Stock zlib before 1.2.4.1 doesn't export the `*64` variants, and early
+// builds without large file support leave them out too.
+#[cfg(all(any(zng, feature = "libc"), any(zng, zlib_1_2_5_1)))]
+extern "C" {
+    #[link_name = if_zng!("zng_adler32_combine", "adler32_combine64")]
+    pub fn adler32_combine64(adler1: z_checksum, adler2: z_checksum, len2: z_off64_t)
+        -> z_checksum;
+    #[link_name = if_zng!("zng_crc32_combine", "crc32_combine64")]
+    pub fn crc32_combine64(crc1: z_checksum, crc2: z_checksum, len2: z_off64_t) -> z_checksum;
+    #[link_name = if_zng!("zng_gzopen", "gzopen64")]
+    pub fn gzopen64(path: *const c_char, mode: *const c_char) -> gzFile;
+    #[link_name = if_zng!("zng_gzoffset", "gzoffset64")]
+    pub fn gzoffset64(file: gzFile) -> z_off64_t;
+    #[link_name = if_zng!("zng_gzseek", "gzseek64")]
+    pub fn gzseek64(file: gzFile, offset: z_off64_t, whence: c_int) -> z_off64_t;
+    #[link_name = if_zng!("zng_gztell", "gztell64")]
+    pub fn gztell64(file: gzFile) -> z_off64_t;
+}
+
+#[cfg(all(any(zng, feature = "libc"), any(zng, zlib_1_2_12)))]
+extern "C" {
+    #[link_name = zng_prefix!(crc32_combine_gen)]
+    pub fn crc32_combine_gen(len2: z_off_t) -> z_checksum;
+    #[link_name = if_zng!("zng_crc32_combine_gen", "crc32_combine_gen64")]
+    pub fn crc32_combine_gen64(len2: z_off64_t) -> z_checksum;
+    #[link_name = zng_prefix!(crc32_combine_op)]
+    pub fn crc32_combine_op(crc1: z_checksum, crc2: z_checksum, op: z_checksum) -> z_checksum;
+}
+
+// `wchar_t` is 16 bits on Windows.
+#[cfg(all(windows, any(zng, feature = "libc"), any(zng, zlib_1_2_7_1)))]
+extern "C" {
+    #[link_name = zng_prefix!(gzopen_w)]
+    pub fn gzopen_w(path: *const u16, mode: *const c_char) -> gzFile;
+}
+
+#[cfg(all(any(zng, feature = "libc"), any(zng, zlib_1_2_9)))]
+extern "C" {
+    #[link_name = zng_prefix!(gzfread)]
+    pub fn gzfread(buf: voidp, size: usize, nitems: usize, file: gzFile) -> usize;
+    #[link_name = zng_prefix!(gzfwrite)]
+    pub fn gzfwrite(buf: voidpc, size: usize, nitems: usize, file: gzFile) -> usize;
+    #[link_name = zng_prefix!(uncompress2)]
+    pub fn uncompress2(
+        dest: *mut Bytef,
+        destLen: *mut z_size,
+        source: *const Bytef,
+        sourceLen: *mut z_size,
+    ) -> c_int;
+}
+
+/// The `ZLIB_VERSION` of the header this crate was built against
+/// (`ZLIBNG_VERSION` for native zlib-ng), or `None` if the build script
+/// couldn't determine it.
+///
+/// For a system library the build script asks the C compiler for the
+/// header's value. Should that fail, it falls back to the `Version` from
+/// zlib's pkg-config file, which normally matches but describes the package
+/// rather than the header.
+///
+/// This is the version the API was gated on at compile time; `zlibVersion`
+/// reports the library that is actually loaded.
+pub const ZLIB_VERSION: Option<&str> = zlib_version();
+
+// ctest2 can't expand `option_env!`, but it ignores function bodies.
+const fn zlib_version() -> Option<&'static str> {
+    option_env!("LIBZ_SYS_ZLIB_VERSION")
+}
+
 pub const Z_NO_FLUSH: c_int = 0;
 pub const Z_PARTIAL_FLUSH: c_int = 1;
 pub const Z_SYNC_FLUSH: c_int = 2;
@@ -429,3 +594,10 @@ pub const Z_ASCII: c_int = Z_TEXT;
 pub const Z_UNKNOWN: c_int = 2;
 
 pub const Z_DEFLATED: c_int = 8;
+
+#[cfg(zng)]
+pub const Z_DEFLATE_LEVEL: zng_deflate_param = 0;
+#[cfg(zng)]
+pub const Z_DEFLATE_STRATEGY: zng_deflate_param = 1;
+#[cfg(zng)]
+pub const Z_DEFLATE_REPRODUCIBLE: zng_deflate_param = 2;
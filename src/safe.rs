@@ -0,0 +1,54 @@
+//! Safe wrappers around the raw bindings.
+//!
+//! These are enabled by the `safe` feature and are built directly on top of the
+//! declarations in the crate root, so they behave the same whether the crate is
+//! linked against stock zlib, zlib-ng in zlib-compat mode, or zlib-ng in native
+//! mode (`libz-ng-sys`).
+
+mod alloc;
+mod checksum;
+mod error;
+#[cfg(any(zng, feature = "libc"))]
+mod gz;
+mod header;
+mod infback;
+mod info;
+mod io;
+#[cfg(any(zng, feature = "libc"))]
+mod oneshot;
+#[cfg(all(feature = "parallel", any(zng, feature = "libc")))]
+mod parallel;
+mod params;
+mod stream;
+
+pub use self::alloc::{
+    blank_stream, deflate_arena_size, inflate_arena_size, new_stream, set_allocator, zalloc,
+    zalloc_with, zfree, zfree_with, Allocator, Arena, MemoryLimit, RustAllocator,
+};
+#[cfg(all(any(zng, feature = "libc"), any(zng, zlib_1_2_12)))]
+pub use self::checksum::CrcCombiner;
+pub use self::checksum::{Adler32, Crc32};
+pub use self::error::{ErrorCode, ZError};
+#[cfg(any(zng, feature = "libc"))]
+pub use self::gz::{gz_write_fmt, GzFile};
+pub use self::header::{ExtraSubfield, GzHeader, HeaderError};
+pub use self::infback::InflateBack;
+pub use self::info::{
+    assert_abi, check_abi, implementation, AbiMismatch, AbiReport, CompileFlags, Implementation,
+    ImplementationInfo,
+};
+pub use self::io::{
+    DeflateDecoder, DeflateEncoder, GzDecoder, GzEncoder, ZlibDecoder, ZlibEncoder,
+};
+#[cfg(any(zng, feature = "libc"))]
+pub use self::oneshot::{compress_to_vec, uncompress_to_vec};
+#[cfg(all(feature = "parallel", any(zng, feature = "libc"), any(unix, windows)))]
+pub use self::parallel::{adler32_file_parallel, crc32_file_parallel};
+#[cfg(all(feature = "parallel", any(zng, feature = "libc")))]
+pub use self::parallel::{adler32_parallel, crc32_parallel};
+#[cfg(zng)]
+pub use self::params::DeflateParam;
+pub use self::params::{
+    DeflateParams, Format, InflateParams, Level, MemLevel, Strategy, WindowBits,
+};
+pub use self::stream::{Deflate, Flush, Inflate, Status};
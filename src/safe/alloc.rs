@@ -0,0 +1,692 @@
+use std::alloc::{self, Layout};
+use std::cell::Cell;
+use std::ffi::CStr;
+use std::marker::PhantomData;
+use std::mem::{self, MaybeUninit};
+use std::os::raw::c_int;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use super::{MemLevel, WindowBits};
+use crate::*;
+
+/// A source of memory for zlib's `zalloc`/`zfree` callbacks.
+///
+/// Both methods are called from inside zlib, so a panic is caught before it
+/// reaches C: a panicking `allocate` is treated as a failed allocation, which
+/// zlib reports as `Z_MEM_ERROR`, and a panicking `deallocate` leaks the
+/// block.
+pub trait Allocator {
+    /// Allocates a block for `layout`, or returns null on failure.
+    fn allocate(&self, layout: Layout) -> *mut u8;
+
+    /// Frees a block returned by `allocate`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have come from `allocate` on this allocator with the same
+    /// `layout`, and must not have been freed already.
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout);
+}
+
+/// The Rust global allocator.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RustAllocator;
+
+impl Allocator for RustAllocator {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        unsafe { alloc::alloc(layout) }
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        alloc::dealloc(ptr, layout)
+    }
+}
+
+// Lets a caller hand one clone to a stream and keep another to inspect, as
+// with `MemoryLimit`.
+impl<A: Allocator + ?Sized> Allocator for Arc<A> {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        (**self).allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        (**self).deallocate(ptr, layout)
+    }
+}
+
+/// An allocator that refuses to hand out more than a fixed number of bytes
+/// at once, and records how much it has handed out.
+///
+/// Once the budget is used up, allocations fail and zlib returns
+/// `Z_MEM_ERROR`. The counts include the few bytes of bookkeeping each block
+/// carries, so they reflect what the stream actually costs. Share it through
+/// an [`Arc`] to read the counts while a stream is using it:
+///
+/// ```no_run
+/// # use std::sync::Arc;
+/// # use libz_sys::safe::{Inflate, InflateParams, MemoryLimit};
+/// let limit = Arc::new(MemoryLimit::new(64 * 1024));
+/// let inflate = Inflate::with_allocator(&InflateParams::new(), Arc::clone(&limit));
+/// println!("inflate state: {} bytes", limit.current());
+/// ```
+#[derive(Debug)]
+pub struct MemoryLimit<A = RustAllocator> {
+    inner: A,
+    limit: usize,
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl MemoryLimit {
+    /// A budget of `limit` bytes from the Rust global allocator.
+    pub fn new(limit: usize) -> MemoryLimit {
+        MemoryLimit::with_allocator(limit, RustAllocator)
+    }
+}
+
+impl<A> MemoryLimit<A> {
+    /// A budget of `limit` bytes from `inner`.
+    pub fn with_allocator(limit: usize, inner: A) -> MemoryLimit<A> {
+        MemoryLimit {
+            inner,
+            limit,
+            current: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    /// The budget, in bytes.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// The number of bytes currently allocated.
+    pub fn current(&self) -> usize {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    /// The most bytes that have been allocated at once.
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+}
+
+impl<A: Allocator> Allocator for MemoryLimit<A> {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        let size = layout.size();
+        let reserved = self
+            .current
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+                current.checked_add(size).filter(|&new| new <= self.limit)
+            });
+        let current = match reserved {
+            Ok(current) => current,
+            Err(_) => return ptr::null_mut(),
+        };
+        let ptr = self.inner.allocate(layout);
+        if ptr.is_null() {
+            self.current.fetch_sub(size, Ordering::Relaxed);
+        } else {
+            self.peak.fetch_max(current + size, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.deallocate(ptr, layout);
+        self.current.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+/// An allocator that carves blocks out of a caller-provided buffer, for
+/// targets without a heap.
+///
+/// Blocks are handed out in order. Freeing the most recent block makes its
+/// space available again; anything else is only reclaimed by
+/// [`Arena::reset`]. That fits zlib, which allocates everything a stream
+/// needs up front and frees it all at the end. Use [`deflate_arena_size`] or
+/// [`inflate_arena_size`] to size the buffer, and [`blank_stream`] with
+/// [`set_allocator`] to set up a stream without touching the global
+/// allocator.
+pub struct Arena<'a> {
+    base: *mut u8,
+    len: usize,
+    used: Cell<usize>,
+    _buf: PhantomData<&'a mut [MaybeUninit<u8>]>,
+}
+
+// The arena has exclusive use of its buffer.
+unsafe impl Send for Arena<'_> {}
+
+impl<'a> Arena<'a> {
+    /// An arena serving allocations from `buf`.
+    pub fn new(buf: &'a mut [MaybeUninit<u8>]) -> Arena<'a> {
+        Arena {
+            base: buf.as_mut_ptr().cast(),
+            len: buf.len(),
+            used: Cell::new(0),
+            _buf: PhantomData,
+        }
+    }
+
+    /// The number of bytes handed out so far, including alignment padding.
+    pub fn used(&self) -> usize {
+        self.used.get()
+    }
+
+    /// The size of the buffer.
+    pub fn capacity(&self) -> usize {
+        self.len
+    }
+
+    /// Makes the whole buffer available again. Requiring `&mut self` ensures
+    /// no stream is still using it.
+    pub fn reset(&mut self) {
+        self.used.set(0);
+    }
+}
+
+impl Allocator for Arena<'_> {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        let used = self.used.get();
+        let start = unsafe { self.base.add(used) };
+        let start = used + start.align_offset(layout.align());
+        match start.checked_add(layout.size()) {
+            Some(end) if end <= self.len => {
+                self.used.set(end);
+                unsafe { self.base.add(start) }
+            }
+            _ => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        let start = ptr.offset_from(self.base) as usize;
+        if start + layout.size() == self.used.get() {
+            self.used.set(start);
+        }
+    }
+}
+
+/// How big an [`Arena`] must be to initialize and run one deflate stream with
+/// these parameters.
+///
+/// The size is exact for the linked library: it replays the allocations
+/// `deflateInit2_` makes, including the header [`zalloc_with`] puts on each
+/// block and the padding between blocks. It assumes the buffer starts on a
+/// 16-byte boundary; add 15 bytes if it might not.
+pub fn deflate_arena_size(window_bits: WindowBits, mem_level: MemLevel) -> usize {
+    arena_size(|stream| unsafe {
+        let ret = deflateInit2_(
+            stream,
+            Z_DEFAULT_COMPRESSION,
+            Z_DEFLATED,
+            window_bits.as_raw(),
+            mem_level.as_raw(),
+            Z_DEFAULT_STRATEGY,
+            zlibVersion(),
+            mem::size_of::<z_stream>() as c_int,
+        );
+        if ret == Z_OK {
+            deflateEnd(stream);
+        }
+    })
+}
+
+/// How big an [`Arena`] must be to initialize and run one inflate stream
+/// with this window size, in any format.
+///
+/// Exact in the same way as [`deflate_arena_size`], with the same alignment
+/// assumption.
+pub fn inflate_arena_size(window_bits: WindowBits) -> usize {
+    let size = arena_size(|stream| unsafe {
+        let ret = inflateInit2_(
+            stream,
+            window_bits.as_raw(),
+            zlibVersion(),
+            mem::size_of::<z_stream>() as c_int,
+        );
+        if ret == Z_OK {
+            inflateEnd(stream);
+        }
+    });
+    // zlib-ng allocates the window along with the state, but stock zlib only
+    // allocates it once `inflate` first needs it.
+    let version = unsafe { CStr::from_ptr(zlibVersion()) };
+    let is_ng = cfg!(zng) || version.to_bytes().windows(7).any(|w| w == b"zlib-ng");
+    if is_ng {
+        size
+    } else {
+        align_up(size) + (1 << window_bits.as_raw()) + ALIGN
+    }
+}
+
+// Allocation sizes are recorded while serving them from a small scratch
+// arena. A request that doesn't fit fails, which makes the init function
+// give up, but by then zlib has asked for every block: stock zlib requests
+// all of them before checking any, and zlib-ng requests a single block.
+fn arena_size(init: impl FnOnce(&mut z_stream)) -> usize {
+    struct Probe<'a> {
+        scratch: Arena<'a>,
+        sizes: Cell<[usize; 8]>,
+        count: Cell<usize>,
+    }
+
+    impl Allocator for Probe<'_> {
+        fn allocate(&self, layout: Layout) -> *mut u8 {
+            let mut sizes = self.sizes.get();
+            if let Some(slot) = sizes.get_mut(self.count.get()) {
+                *slot = layout.size();
+                self.sizes.set(sizes);
+                self.count.set(self.count.get() + 1);
+            }
+            self.scratch.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+            self.scratch.deallocate(ptr, layout)
+        }
+    }
+
+    let mut buf = [MaybeUninit::uninit(); 16 * 1024];
+    let probe = Probe {
+        scratch: Arena::new(&mut buf),
+        sizes: Cell::new([0; 8]),
+        count: Cell::new(0),
+    };
+    let mut stream = blank_stream();
+    unsafe { set_allocator(&mut stream, &probe) };
+    init(&mut stream);
+    probe.sizes.get()[..probe.count.get()]
+        .iter()
+        .fold(0, |used, size| align_up(used) + size)
+}
+
+fn align_up(n: usize) -> usize {
+    (n + ALIGN - 1) & !(ALIGN - 1)
+}
+
+/// An `alloc_func` that allocates from the Rust global allocator. `opaque` is
+/// ignored.
+pub extern "C" fn zalloc(_opaque: voidpf, items: uInt, size: uInt) -> voidpf {
+    unsafe { allocate(&RustAllocator, items, size) }
+}
+
+/// The `free_func` matching [`zalloc`].
+///
+/// # Safety
+///
+/// `address` must be null or a block returned by [`zalloc`] that hasn't been
+/// freed yet.
+pub unsafe extern "C" fn zfree(_opaque: voidpf, address: voidpf) {
+    deallocate(&RustAllocator, address)
+}
+
+/// An `alloc_func` that allocates from the `A` that `opaque` points to.
+///
+/// # Safety
+///
+/// `opaque` must point to a live `A`.
+pub unsafe extern "C" fn zalloc_with<A: Allocator>(
+    opaque: voidpf,
+    items: uInt,
+    size: uInt,
+) -> voidpf {
+    allocate(&*(opaque as *const A), items, size)
+}
+
+/// The `free_func` matching [`zalloc_with`].
+///
+/// # Safety
+///
+/// `opaque` must point to the `A` that `address` was allocated from, and
+/// `address` must be null or a block returned by [`zalloc_with`] that hasn't
+/// been freed yet.
+pub unsafe extern "C" fn zfree_with<A: Allocator>(opaque: voidpf, address: voidpf) {
+    deallocate(&*(opaque as *const A), address)
+}
+
+/// A `z_stream` ready to be passed to one of the `*Init*` functions, with
+/// [`zalloc`] and [`zfree`] installed.
+///
+/// `alloc_func` can't be null on the Rust side, and `Z_SOLO` builds have no
+/// default allocator to fall back to anyway, so every stream should start
+/// out with real callbacks. The stream is boxed because zlib keeps a pointer
+/// back to it once initialized.
+pub fn new_stream() -> Box<z_stream> {
+    Box::new(blank_stream())
+}
+
+/// The unboxed form of [`new_stream`], for when there is no heap to put it
+/// on.
+///
+/// The stream must not be moved once it has been initialized.
+pub fn blank_stream() -> z_stream {
+    z_stream {
+        next_in: ptr::null_mut(),
+        avail_in: 0,
+        total_in: 0,
+        next_out: ptr::null_mut(),
+        avail_out: 0,
+        total_out: 0,
+        msg: ptr::null_mut(),
+        state: ptr::null_mut(),
+        zalloc,
+        zfree,
+        opaque: ptr::null_mut(),
+        data_type: 0,
+        adler: 0,
+        reserved: 0,
+    }
+}
+
+/// Points `stream` at `allocator` through [`zalloc_with`] and [`zfree_with`].
+///
+/// # Safety
+///
+/// This must happen before the stream is initialized, and `allocator` must
+/// stay valid until after the matching `*End` call.
+pub unsafe fn set_allocator<A: Allocator>(stream: &mut z_stream, allocator: *const A) {
+    stream.zalloc = zalloc_with::<A>;
+    stream.zfree = zfree_with::<A>;
+    stream.opaque = allocator as voidpf;
+}
+
+// `zfree` isn't told the size of the allocation, so every block starts with a
+// header recording it. The header is a full alignment unit so the pointer
+// handed to zlib stays aligned.
+const ALIGN: usize = 16;
+
+unsafe fn allocate<A: Allocator>(allocator: &A, items: uInt, size: uInt) -> voidpf {
+    let size = match (items as usize)
+        .checked_mul(size as usize)
+        .and_then(|size| size.checked_add(ALIGN))
+    {
+        Some(size) => size,
+        None => return ptr::null_mut(),
+    };
+    let layout = match Layout::from_size_align(size, ALIGN) {
+        Ok(layout) => layout,
+        Err(_) => return ptr::null_mut(),
+    };
+    let ptr = match panic::catch_unwind(AssertUnwindSafe(|| allocator.allocate(layout))) {
+        Ok(ptr) if !ptr.is_null() => ptr,
+        _ => return ptr::null_mut(),
+    };
+    (ptr as *mut usize).write(size);
+    ptr.add(ALIGN) as voidpf
+}
+
+unsafe fn deallocate<A: Allocator>(allocator: &A, address: voidpf) {
+    if address.is_null() {
+        return;
+    }
+    let ptr = (address as *mut u8).sub(ALIGN);
+    let size = (ptr as *const usize).read();
+    let layout = Layout::from_size_align_unchecked(size, ALIGN);
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| allocator.deallocate(ptr, layout)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::safe::{Deflate, DeflateParams, ErrorCode, Flush, Inflate, InflateParams};
+
+    /// Counts the blocks that are live.
+    #[derive(Default)]
+    struct Counting {
+        live: AtomicUsize,
+        total: AtomicUsize,
+    }
+
+    impl Allocator for Counting {
+        fn allocate(&self, layout: Layout) -> *mut u8 {
+            self.live.fetch_add(1, Ordering::Relaxed);
+            self.total.fetch_add(1, Ordering::Relaxed);
+            RustAllocator.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+            self.live.fetch_sub(1, Ordering::Relaxed);
+            RustAllocator.deallocate(ptr, layout)
+        }
+    }
+
+    struct Panicking;
+
+    impl Allocator for Panicking {
+        fn allocate(&self, _: Layout) -> *mut u8 {
+            panic!("no memory for you");
+        }
+
+        unsafe fn deallocate(&self, _: *mut u8, _: Layout) {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn zalloc_and_zfree() {
+        let ptr = zalloc(ptr::null_mut(), 10, 100);
+        assert!(!ptr.is_null());
+        assert_eq!(ptr as usize % ALIGN, 0);
+        unsafe {
+            ptr::write_bytes(ptr as *mut u8, 0xaa, 1000);
+            zfree(ptr::null_mut(), ptr);
+            zfree(ptr::null_mut(), ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn zalloc_overflow_fails() {
+        assert!(zalloc(ptr::null_mut(), uInt::MAX, uInt::MAX).is_null());
+    }
+
+    #[test]
+    fn streams_use_the_allocator() {
+        let counting = Arc::new(Counting::default());
+        let mut deflate =
+            Deflate::with_allocator(&DeflateParams::new(), Arc::clone(&counting)).unwrap();
+        let inflate =
+            Inflate::with_allocator(&InflateParams::new(), Arc::clone(&counting)).unwrap();
+        let mut out = [0; 64];
+        deflate.compress(b"data", &mut out, Flush::Finish).unwrap();
+        assert!(counting.total.load(Ordering::Relaxed) >= 2);
+        drop((deflate, inflate));
+        assert_eq!(counting.live.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn panicking_allocator_is_a_memory_error() {
+        let err = Deflate::with_allocator(&DeflateParams::new(), Panicking)
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), ErrorCode::Mem);
+        let err = Inflate::with_allocator(&InflateParams::new(), Panicking)
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), ErrorCode::Mem);
+    }
+
+    #[test]
+    fn memory_limit_counts_blocks() {
+        let limit = MemoryLimit::new(100);
+        let a = limit.allocate(Layout::from_size_align(60, 1).unwrap());
+        assert!(!a.is_null());
+        assert!(limit
+            .allocate(Layout::from_size_align(41, 1).unwrap())
+            .is_null());
+        let b = limit.allocate(Layout::from_size_align(40, 1).unwrap());
+        assert!(!b.is_null());
+        assert_eq!(limit.current(), 100);
+        unsafe {
+            limit.deallocate(a, Layout::from_size_align(60, 1).unwrap());
+            limit.deallocate(b, Layout::from_size_align(40, 1).unwrap());
+        }
+        assert_eq!(limit.current(), 0);
+        assert_eq!(limit.peak(), 100);
+        assert_eq!(limit.limit(), 100);
+    }
+
+    #[test]
+    fn memory_limit_tracks_streams() {
+        let limit = Arc::new(MemoryLimit::new(1 << 20));
+        let deflate = Deflate::with_allocator(&DeflateParams::new(), Arc::clone(&limit)).unwrap();
+        let in_use = limit.current();
+        assert!(in_use > 0);
+        drop(deflate);
+        assert_eq!(limit.current(), 0);
+        assert_eq!(limit.peak(), in_use);
+    }
+
+    #[test]
+    fn memory_limit_too_small() {
+        let limit = Arc::new(MemoryLimit::new(1024));
+        let err = Deflate::with_allocator(&DeflateParams::new(), Arc::clone(&limit))
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), ErrorCode::Mem);
+        assert_eq!(limit.current(), 0);
+    }
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i % 251) as u8 ^ (i / 1024) as u8)
+            .collect()
+    }
+
+    // `len` bytes starting on a 16-byte boundary.
+    fn aligned(storage: &mut Vec<MaybeUninit<u8>>, len: usize) -> &mut [MaybeUninit<u8>] {
+        storage.resize(len + ALIGN, MaybeUninit::uninit());
+        let offset = storage.as_ptr().align_offset(ALIGN);
+        &mut storage[offset..offset + len]
+    }
+
+    // Compresses `input` with a stream living in `buf`.
+    fn deflate_in(
+        buf: &mut [MaybeUninit<u8>],
+        window_bits: c_int,
+        mem_level: c_int,
+        input: &[u8],
+    ) -> Result<Vec<u8>, c_int> {
+        let arena = Arena::new(buf);
+        let mut stream = blank_stream();
+        let mut out = vec![0; input.len() + 1024];
+        unsafe {
+            set_allocator(&mut stream, &arena);
+            let ret = deflateInit2_(
+                &mut stream,
+                Z_DEFAULT_COMPRESSION,
+                Z_DEFLATED,
+                window_bits,
+                mem_level,
+                Z_DEFAULT_STRATEGY,
+                zlibVersion(),
+                mem::size_of::<z_stream>() as c_int,
+            );
+            if ret != Z_OK {
+                return Err(ret);
+            }
+            stream.next_in = input.as_ptr() as *mut _;
+            stream.avail_in = input.len() as uInt;
+            stream.next_out = out.as_mut_ptr();
+            stream.avail_out = out.len() as uInt;
+            let ret = deflate(&mut stream, Z_FINISH);
+            out.truncate(out.len() - stream.avail_out as usize);
+            deflateEnd(&mut stream);
+            match ret {
+                Z_STREAM_END => Ok(out),
+                ret => Err(ret),
+            }
+        }
+    }
+
+    // Decompresses `input` a little at a time, so stock zlib needs its
+    // window, with a stream living in `buf`.
+    fn inflate_in(
+        buf: &mut [MaybeUninit<u8>],
+        window_bits: c_int,
+        input: &[u8],
+    ) -> Result<Vec<u8>, c_int> {
+        let arena = Arena::new(buf);
+        let mut stream = blank_stream();
+        let mut out = Vec::new();
+        let mut chunk = [0; 1000];
+        unsafe {
+            set_allocator(&mut stream, &arena);
+            let ret = inflateInit2_(
+                &mut stream,
+                window_bits,
+                zlibVersion(),
+                mem::size_of::<z_stream>() as c_int,
+            );
+            if ret != Z_OK {
+                return Err(ret);
+            }
+            stream.next_in = input.as_ptr() as *mut _;
+            stream.avail_in = input.len() as uInt;
+            let ret = loop {
+                stream.next_out = chunk.as_mut_ptr();
+                stream.avail_out = chunk.len() as uInt;
+                let ret = inflate(&mut stream, Z_NO_FLUSH);
+                out.extend_from_slice(&chunk[..chunk.len() - stream.avail_out as usize]);
+                if ret != Z_OK {
+                    break ret;
+                }
+            };
+            inflateEnd(&mut stream);
+            match ret {
+                Z_STREAM_END => Ok(out),
+                ret => Err(ret),
+            }
+        }
+    }
+
+    #[test]
+    fn deflate_arena_size_is_exact() {
+        let input = sample(10_000);
+        let mut storage = Vec::new();
+        for window_bits in 8..=15 {
+            for mem_level in 1..=9 {
+                let size = deflate_arena_size(
+                    WindowBits::new(window_bits).unwrap(),
+                    MemLevel::new(mem_level).unwrap(),
+                );
+                let (window_bits, mem_level) = (window_bits as c_int, mem_level as c_int);
+                let buf = aligned(&mut storage, size);
+                assert!(deflate_in(buf, window_bits, mem_level, &input).is_ok());
+                let buf = aligned(&mut storage, size - 1);
+                assert_eq!(
+                    deflate_in(buf, window_bits, mem_level, &input),
+                    Err(Z_MEM_ERROR)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn inflate_arena_size_is_exact() {
+        let input = sample(100_000);
+        let mut storage = Vec::new();
+        // Compressing with a window of 8 writes a header claiming 9, which
+        // inflate then rejects as too big for a window of 8.
+        for window_bits in 9..=15 {
+            let params = DeflateParams::new().window_bits(WindowBits::new(window_bits).unwrap());
+            let mut compressed = vec![0; input.len() + 1024];
+            let mut deflate = Deflate::with_params(&params).unwrap();
+            let (_, len) = deflate
+                .compress(&input, &mut compressed, Flush::Finish)
+                .unwrap();
+            compressed.truncate(len);
+
+            let size = inflate_arena_size(WindowBits::new(window_bits).unwrap());
+            let window_bits = window_bits as c_int;
+            let buf = aligned(&mut storage, size);
+            assert_eq!(inflate_in(buf, window_bits, &compressed).unwrap(), input);
+            let buf = aligned(&mut storage, size - 1);
+            assert_eq!(inflate_in(buf, window_bits, &compressed), Err(Z_MEM_ERROR));
+        }
+    }
+}
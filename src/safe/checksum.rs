@@ -0,0 +1,357 @@
+#[cfg(all(any(zng, feature = "libc"), any(zng, zlib_1_2_12)))]
+use std::collections::HashMap;
+use std::hash::Hasher;
+
+use crate::*;
+
+// `adler32` and `crc32` take a `uInt` length, so longer buffers are fed in
+// pieces.
+const MAX_CHUNK: usize = uInt::MAX as usize;
+
+/// A running CRC-32, as used by gzip.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Crc32 {
+    sum: u32,
+    amount: u64,
+}
+
+impl Crc32 {
+    /// The checksum of no data.
+    pub fn new() -> Crc32 {
+        Crc32::from_value(0)
+    }
+
+    /// Resumes a checksum from a previously finalized value.
+    pub fn from_value(sum: u32) -> Crc32 {
+        Crc32 { sum, amount: 0 }
+    }
+
+    /// Feeds `data` into the checksum.
+    // `crc32` returns `uLong` or `uint32_t` depending on the backend.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn update(&mut self, data: &[u8]) {
+        let mut sum = z_checksum::from(self.sum);
+        for chunk in data.chunks(MAX_CHUNK) {
+            sum = unsafe { crc32(sum, chunk.as_ptr(), chunk.len() as uInt) };
+        }
+        self.sum = sum as u32;
+        self.amount += data.len() as u64;
+    }
+
+    /// The checksum of everything fed in so far.
+    pub fn finalize(&self) -> u32 {
+        self.sum
+    }
+
+    /// The number of bytes fed in with [`Crc32::update`].
+    pub fn amount(&self) -> u64 {
+        self.amount
+    }
+
+    /// Extends this checksum with `other`, the CRC-32 of a further `len2`
+    /// bytes, as if those bytes had been fed in with [`Crc32::update`].
+    #[cfg(any(zng, feature = "libc"))]
+    #[allow(clippy::unnecessary_cast)]
+    pub fn combine(&mut self, other: u32, len2: u64) {
+        // `z_off_t` may be 32 bits. Combining with a CRC of zero shifts the
+        // first CRC over `len2` zero bytes, so a long `len2` can be applied
+        // in steps before folding in `other`.
+        let max = z_off_t::MAX as u64;
+        let mut sum = z_checksum::from(self.sum);
+        let mut remaining = len2;
+        while remaining > max {
+            sum = unsafe { crc32_combine(sum, 0, max as z_off_t) };
+            remaining -= max;
+        }
+        sum = unsafe { crc32_combine(sum, z_checksum::from(other), remaining as z_off_t) };
+        self.sum = sum as u32;
+        self.amount += len2;
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Crc32 {
+        Crc32::new()
+    }
+}
+
+impl Hasher for Crc32 {
+    fn write(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        self.finalize() as u64
+    }
+}
+
+/// Combines CRC-32s of blocks whose lengths repeat.
+///
+/// [`Crc32::combine`] works out how to shift a checksum over `len2` bytes
+/// every time it is called. This computes that operator once per distinct
+/// length with `crc32_combine_gen64` and applies it with the much cheaper
+/// `crc32_combine_op`, which pays off when stitching together many blocks of
+/// the same size.
+///
+/// Requires zlib 1.2.12 or later, or zlib-ng.
+#[cfg(all(any(zng, feature = "libc"), any(zng, zlib_1_2_12)))]
+#[derive(Clone, Debug, Default)]
+pub struct CrcCombiner {
+    ops: HashMap<u64, u32>,
+}
+
+#[cfg(all(any(zng, feature = "libc"), any(zng, zlib_1_2_12)))]
+impl CrcCombiner {
+    /// A combiner with no cached operators.
+    pub fn new() -> CrcCombiner {
+        CrcCombiner::default()
+    }
+
+    /// Returns the CRC-32 of two blocks laid end to end, given the CRC-32
+    /// `crc1` of the first, and the CRC-32 `crc2` and length `len2` of the
+    /// second.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn combine(&mut self, crc1: u32, crc2: u32, len2: u64) -> u32 {
+        let op = z_checksum::from(self.operator(len2));
+        let crc = unsafe { crc32_combine_op(z_checksum::from(crc1), z_checksum::from(crc2), op) };
+        crc as u32
+    }
+
+    /// Folds `other`, the checksum of the next `len2` bytes, into `crc`.
+    pub fn extend(&mut self, crc: &mut Crc32, other: u32, len2: u64) {
+        crc.sum = self.combine(crc.sum, other, len2);
+        crc.amount += len2;
+    }
+
+    /// The operator that shifts a CRC-32 over `len2` bytes, for use with
+    /// `crc32_combine_op`.
+    pub fn operator(&mut self, len2: u64) -> u32 {
+        *self.ops.entry(len2).or_insert_with(|| generate(len2))
+    }
+
+    /// Drops all cached operators.
+    pub fn clear(&mut self) {
+        self.ops.clear();
+    }
+}
+
+// Builds the operator for `len2` bytes. Should `len2` not fit in a
+// `z_off64_t`, operators for shorter runs are composed: applying the operator
+// for `b` to the operator for `a` yields the one for `a + b`.
+#[cfg(all(any(zng, feature = "libc"), any(zng, zlib_1_2_12)))]
+#[allow(clippy::unnecessary_cast)]
+fn generate(len2: u64) -> u32 {
+    let max = z_off64_t::MAX as u64;
+    let mut remaining = len2;
+    let mut op = None;
+    loop {
+        let step = remaining.min(max);
+        let next = unsafe { crc32_combine_gen64(step as z_off64_t) };
+        op = Some(match op {
+            Some(op) => unsafe { crc32_combine_op(op, 0, next) },
+            None => next,
+        });
+        remaining -= step;
+        if remaining == 0 {
+            return op.unwrap() as u32;
+        }
+    }
+}
+
+/// A running Adler-32, as used by the zlib format.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Adler32 {
+    sum: u32,
+    amount: u64,
+}
+
+impl Adler32 {
+    /// The checksum of no data.
+    pub fn new() -> Adler32 {
+        Adler32::from_value(1)
+    }
+
+    /// Resumes a checksum from a previously finalized value.
+    pub fn from_value(sum: u32) -> Adler32 {
+        Adler32 { sum, amount: 0 }
+    }
+
+    /// Feeds `data` into the checksum.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn update(&mut self, data: &[u8]) {
+        let mut sum = z_checksum::from(self.sum);
+        for chunk in data.chunks(MAX_CHUNK) {
+            sum = unsafe { adler32(sum, chunk.as_ptr(), chunk.len() as uInt) };
+        }
+        self.sum = sum as u32;
+        self.amount += data.len() as u64;
+    }
+
+    /// The checksum of everything fed in so far.
+    pub fn finalize(&self) -> u32 {
+        self.sum
+    }
+
+    /// The number of bytes fed in with [`Adler32::update`].
+    pub fn amount(&self) -> u64 {
+        self.amount
+    }
+
+    /// Extends this checksum with `other`, the Adler-32 of a further `len2`
+    /// bytes, as if those bytes had been fed in with [`Adler32::update`].
+    #[cfg(any(zng, feature = "libc"))]
+    #[allow(clippy::unnecessary_cast)]
+    pub fn combine(&mut self, other: u32, len2: u64) {
+        // Only `len2` modulo 65521 affects the result, which always fits in a
+        // `z_off_t`.
+        let len = (len2 % 65521) as z_off_t;
+        let sum =
+            unsafe { adler32_combine(z_checksum::from(self.sum), z_checksum::from(other), len) };
+        self.sum = sum as u32;
+        self.amount += len2;
+    }
+}
+
+impl Default for Adler32 {
+    fn default() -> Adler32 {
+        Adler32::new()
+    }
+}
+
+impl Hasher for Adler32 {
+    fn write(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        self.finalize() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i % 251) as u8 ^ (i / 1024) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn check_values() {
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.finalize(), 0xcbf4_3926);
+        assert_eq!(crc.amount(), 9);
+
+        let mut adler = Adler32::new();
+        adler.update(b"123456789");
+        assert_eq!(adler.finalize(), 0x091e_01de);
+        assert_eq!(Adler32::new().finalize(), 1);
+        assert_eq!(Crc32::new().finalize(), 0);
+    }
+
+    #[test]
+    fn incremental_matches_one_shot() {
+        let data = sample(10_000);
+        let mut whole = Crc32::new();
+        whole.update(&data);
+        let mut pieces = Crc32::new();
+        for chunk in data.chunks(333) {
+            pieces.update(chunk);
+        }
+        assert_eq!(pieces, whole);
+
+        let mut resumed = Adler32::from_value({
+            let mut first = Adler32::new();
+            first.update(&data[..5_000]);
+            first.finalize()
+        });
+        resumed.update(&data[5_000..]);
+        let mut whole = Adler32::new();
+        whole.update(&data);
+        assert_eq!(resumed.finalize(), whole.finalize());
+    }
+
+    #[test]
+    fn hasher() {
+        let mut crc = Crc32::new();
+        Hasher::write(&mut crc, b"123456789");
+        assert_eq!(Hasher::finish(&crc), 0xcbf4_3926);
+    }
+
+    #[cfg(any(zng, feature = "libc"))]
+    #[test]
+    fn combine_matches_serial() {
+        let data = sample(100_000);
+        for &split in &[0, 1, 4_096, 65_521, 65_522, 99_999, 100_000] {
+            let (a, b) = data.split_at(split);
+
+            let mut crc = Crc32::new();
+            crc.update(a);
+            let mut crc_b = Crc32::new();
+            crc_b.update(b);
+            crc.combine(crc_b.finalize(), b.len() as u64);
+            let mut serial = Crc32::new();
+            serial.update(&data);
+            assert_eq!(crc, serial, "crc32 split at {}", split);
+
+            let mut adler = Adler32::new();
+            adler.update(a);
+            let mut adler_b = Adler32::new();
+            adler_b.update(b);
+            adler.combine(adler_b.finalize(), b.len() as u64);
+            let mut serial = Adler32::new();
+            serial.update(&data);
+            assert_eq!(adler, serial, "adler32 split at {}", split);
+        }
+    }
+
+    #[cfg(all(any(zng, feature = "libc"), any(zng, zlib_1_2_12)))]
+    #[test]
+    fn combiner_matches_serial() {
+        let data = sample(100_000);
+        let mut combiner = CrcCombiner::new();
+        let mut crc = Crc32::new();
+        for block in data.chunks(4_096) {
+            let mut block_crc = Crc32::new();
+            block_crc.update(block);
+            combiner.extend(&mut crc, block_crc.finalize(), block.len() as u64);
+        }
+        let mut serial = Crc32::new();
+        serial.update(&data);
+        assert_eq!(crc, serial);
+        // One operator for the full blocks and one for the short last block.
+        assert_eq!(combiner.ops.len(), 2);
+
+        let mut first = Crc32::new();
+        first.update(&data[..1_000]);
+        let mut second = Crc32::new();
+        second.update(&data[1_000..]);
+        let mut expected = first;
+        expected.combine(second.finalize(), 99_000);
+        assert_eq!(
+            combiner.combine(first.finalize(), second.finalize(), 99_000),
+            expected.finalize()
+        );
+
+        combiner.clear();
+        assert!(combiner.ops.is_empty());
+    }
+
+    #[cfg(all(any(zng, feature = "libc"), any(zng, zlib_1_2_12)))]
+    #[test]
+    #[allow(clippy::unnecessary_cast)]
+    fn long_operators_are_composed() {
+        let max = z_off64_t::MAX as u64;
+        let op = |len: u64| unsafe { crc32_combine_gen64(len as z_off64_t) };
+        let compose = |a, b| unsafe { crc32_combine_op(a, 0, b) } as u32;
+        assert_eq!(generate(max), op(max) as u32);
+        assert_eq!(generate(max + 10), compose(op(max), op(10)));
+        assert_eq!(
+            generate(u64::MAX),
+            compose(compose(op(max), op(max)) as z_checksum, op(1))
+        );
+    }
+}
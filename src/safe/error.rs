@@ -0,0 +1,197 @@
+use std::error::Error;
+use std::ffi::CStr;
+use std::fmt;
+use std::io;
+use std::os::raw::c_int;
+
+use crate::*;
+
+/// The `Z_*` return code behind a [`ZError`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// `Z_ERRNO`: a file operation failed; see `errno`.
+    Errno,
+    /// `Z_STREAM_ERROR`: the stream state or a parameter was invalid.
+    Stream,
+    /// `Z_DATA_ERROR`: the input was corrupt or incomplete.
+    Data,
+    /// `Z_MEM_ERROR`: not enough memory.
+    Mem,
+    /// `Z_BUF_ERROR`: no progress was possible.
+    Buf,
+    /// `Z_VERSION_ERROR`: the library is incompatible with the caller.
+    Version,
+    /// Any other value, as returned by the library.
+    Other(c_int),
+}
+
+impl ErrorCode {
+    /// Maps a raw return code onto its variant.
+    pub fn from_raw(code: c_int) -> ErrorCode {
+        match code {
+            Z_ERRNO => ErrorCode::Errno,
+            Z_STREAM_ERROR => ErrorCode::Stream,
+            Z_DATA_ERROR => ErrorCode::Data,
+            Z_MEM_ERROR => ErrorCode::Mem,
+            Z_BUF_ERROR => ErrorCode::Buf,
+            Z_VERSION_ERROR => ErrorCode::Version,
+            code => ErrorCode::Other(code),
+        }
+    }
+
+    /// The raw return code.
+    pub fn as_raw(self) -> c_int {
+        match self {
+            ErrorCode::Errno => Z_ERRNO,
+            ErrorCode::Stream => Z_STREAM_ERROR,
+            ErrorCode::Data => Z_DATA_ERROR,
+            ErrorCode::Mem => Z_MEM_ERROR,
+            ErrorCode::Buf => Z_BUF_ERROR,
+            ErrorCode::Version => Z_VERSION_ERROR,
+            ErrorCode::Other(code) => code,
+        }
+    }
+
+    fn description(self) -> &'static str {
+        // Same wording as zlib's own `z_errmsg` table.
+        match self {
+            ErrorCode::Errno => "file error",
+            ErrorCode::Stream => "stream error",
+            ErrorCode::Data => "data error",
+            ErrorCode::Mem => "insufficient memory",
+            ErrorCode::Buf => "buffer error",
+            ErrorCode::Version => "incompatible version",
+            ErrorCode::Other(_) => "unknown error",
+        }
+    }
+}
+
+/// An error returned by zlib, along with the message it left in
+/// `z_stream.msg`, if any.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ZError {
+    code: ErrorCode,
+    message: Option<String>,
+}
+
+impl ZError {
+    /// An error for `code` with no message.
+    pub fn new(code: c_int) -> ZError {
+        ZError {
+            code: ErrorCode::from_raw(code),
+            message: None,
+        }
+    }
+
+    /// An error for `code` with the given message.
+    pub fn with_message(code: c_int, message: impl Into<String>) -> ZError {
+        ZError {
+            code: ErrorCode::from_raw(code),
+            message: Some(message.into()),
+        }
+    }
+
+    /// An error for `code`, copying the message from `stream.msg`.
+    ///
+    /// # Safety
+    ///
+    /// `stream.msg` must be null or point to a NUL-terminated string, as is
+    /// the case for any stream that has only been touched by zlib.
+    pub unsafe fn from_stream(code: c_int, stream: &z_stream) -> ZError {
+        let message = if stream.msg.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr(stream.msg).to_string_lossy().into_owned())
+        };
+        ZError {
+            code: ErrorCode::from_raw(code),
+            message,
+        }
+    }
+
+    /// The kind of error.
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    /// The message zlib gave for this error, if any.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+}
+
+impl fmt::Display for ZError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.message {
+            Some(message) => write!(f, "{}: {}", self.code.description(), message),
+            None => f.write_str(self.code.description()),
+        }
+    }
+}
+
+impl Error for ZError {}
+
+impl From<ZError> for io::Error {
+    fn from(e: ZError) -> io::Error {
+        let kind = match e.code {
+            ErrorCode::Data => io::ErrorKind::InvalidData,
+            ErrorCode::Mem => io::ErrorKind::OutOfMemory,
+            _ => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::safe::{Flush, Format, Inflate};
+
+    #[test]
+    fn captures_stream_message() {
+        let mut inflate = Inflate::new(Format::Zlib).unwrap();
+        let mut out = [0; 16];
+        let err = inflate
+            .decompress(b"not zlib data", &mut out, Flush::None)
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Data);
+        assert_eq!(err.message(), Some("incorrect header check"));
+        assert_eq!(err.to_string(), "data error: incorrect header check");
+    }
+
+    #[test]
+    fn without_message() {
+        let err = ZError::new(Z_MEM_ERROR);
+        assert_eq!(err.code(), ErrorCode::Mem);
+        assert_eq!(err.message(), None);
+        assert_eq!(err.to_string(), "insufficient memory");
+    }
+
+    #[test]
+    fn raw_codes_round_trip() {
+        for code in [
+            Z_ERRNO,
+            Z_STREAM_ERROR,
+            Z_DATA_ERROR,
+            Z_MEM_ERROR,
+            Z_BUF_ERROR,
+            Z_VERSION_ERROR,
+            7,
+        ] {
+            assert_eq!(ErrorCode::from_raw(code).as_raw(), code);
+        }
+        assert_eq!(ErrorCode::from_raw(7), ErrorCode::Other(7));
+    }
+
+    #[test]
+    fn converts_to_io_error() {
+        let err = io::Error::from(ZError::with_message(Z_DATA_ERROR, "invalid distance"));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "data error: invalid distance");
+        let inner = err.into_inner().unwrap().downcast::<ZError>().unwrap();
+        assert_eq!(inner.code(), ErrorCode::Data);
+
+        let err = io::Error::from(ZError::new(Z_STREAM_ERROR));
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+    }
+}
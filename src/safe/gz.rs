@@ -0,0 +1,603 @@
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::os::raw::{c_int, c_uint};
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, IntoRawFd, OwnedFd};
+use std::path::Path;
+use std::ptr;
+
+use super::ZError;
+use crate::*;
+
+// `gzread` and `gzwrite` take an `unsigned` length but report the result as an
+// `int`, and reject anything that doesn't fit in one, so each call is kept to
+// `INT_MAX` bytes.
+const MAX_CHUNK: usize = c_int::MAX as usize;
+
+const BUF_SIZE: usize = 8 * 1024;
+
+// Formatting buffers that grow past this are dropped after use instead of
+// being kept around for the next call.
+const MAX_RETAINED_FMT: usize = 64 * 1024;
+
+/// An owned `gzFile`, closed with `gzclose` when dropped.
+///
+/// Reads are buffered to support [`BufRead`]; everything else goes straight
+/// to the `gz*` functions.
+///
+/// Formatted output can be written with [`gz_write_fmt`], with `write!`
+/// through [`io::Write`], or through the [`fmt::Write`] adapter returned by
+/// [`GzFile::fmt_writer`].
+pub struct GzFile {
+    file: gzFile,
+    buf: Box<[u8]>,
+    pos: usize,
+    cap: usize,
+    fmt_buf: String,
+    // Whether `gzerror` has reported the file as truncated.
+    truncated: bool,
+}
+
+// A `gzFile` has no thread affinity, and every call goes through `&mut self`.
+unsafe impl Send for GzFile {}
+
+impl GzFile {
+    /// Opens `path` with `gzopen`, or with `gzopen_w` on Windows so that
+    /// any path works.
+    ///
+    /// `mode` is passed through as-is, so it can include a compression level
+    /// or strategy as well as `r`, `w` or `a`, for example `"wb9"`.
+    pub fn open(path: impl AsRef<Path>, mode: &str) -> io::Result<GzFile> {
+        let mode = mode_to_cstring(mode)?;
+        let file = open_path(path.as_ref(), &mode)?;
+        GzFile::from_raw_or_errno(file)
+    }
+
+    /// Takes ownership of `fd` with `gzdopen`.
+    ///
+    /// The descriptor is closed when the returned handle is, or right away if
+    /// `gzdopen` fails.
+    #[cfg(unix)]
+    pub fn from_fd(fd: OwnedFd, mode: &str) -> io::Result<GzFile> {
+        let mode = mode_to_cstring(mode)?;
+        let file = unsafe { gzdopen(fd.as_raw_fd(), mode.as_ptr()) };
+        let file = GzFile::from_raw_or_errno(file)?;
+        // zlib owns the descriptor now, and closes it in `gzclose`.
+        let _ = fd.into_raw_fd();
+        Ok(file)
+    }
+
+    fn from_raw_or_errno(file: gzFile) -> io::Result<GzFile> {
+        if file.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(GzFile {
+            file,
+            buf: vec![0; BUF_SIZE].into_boxed_slice(),
+            pos: 0,
+            cap: 0,
+            fmt_buf: String::new(),
+            truncated: false,
+        })
+    }
+
+    /// Closes the file with `gzclose`, reporting any error that a drop would
+    /// swallow, such as a failure to write the final compressed block.
+    pub fn close(mut self) -> io::Result<()> {
+        // `Drop` skips a null handle, and still frees the buffers.
+        let file = std::mem::replace(&mut self.file, ptr::null_mut());
+        match unsafe { gzclose(file) } {
+            Z_OK => Ok(()),
+            Z_ERRNO => Err(io::Error::last_os_error()),
+            ret => Err(ZError::new(ret).into()),
+        }
+    }
+
+    /// An adapter implementing [`fmt::Write`], for code that formats into a
+    /// `fmt::Write` rather than an `io::Write`.
+    ///
+    /// Each `write!` is formatted in full and handed to `gzwrite` in one
+    /// piece, as with [`gz_write_fmt`].
+    pub fn fmt_writer(&mut self) -> impl fmt::Write + '_ {
+        FmtWriter { file: self }
+    }
+
+    /// The underlying handle, for calling functions this type doesn't wrap.
+    pub fn as_raw(&mut self) -> gzFile {
+        self.file
+    }
+
+    /// The error recorded by `gzerror`, if the last operation failed.
+    ///
+    /// This is where the cause of a [`fmt::Error`] from
+    /// [`GzFile::fmt_writer`] can be found.
+    pub fn last_error(&self) -> Option<io::Error> {
+        let mut errnum = Z_OK;
+        unsafe { gzerror(self.file, &mut errnum) };
+        if errnum == Z_OK {
+            None
+        } else {
+            Some(self.error())
+        }
+    }
+
+    /// The last error recorded by `gzerror`.
+    fn error(&self) -> io::Error {
+        let mut errnum = 0;
+        let msg = unsafe { gzerror(self.file, &mut errnum) };
+        if errnum == Z_ERRNO {
+            return io::Error::last_os_error();
+        }
+        // Reading from a file opened for writing, or the other way around,
+        // fails without recording anything.
+        if errnum == Z_OK {
+            return ZError::with_message(Z_STREAM_ERROR, "file is not open in this mode").into();
+        }
+        let msg = if msg.is_null() {
+            String::new()
+        } else {
+            unsafe { CStr::from_ptr(msg) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        ZError::with_message(errnum, msg).into()
+    }
+
+    /// Reads straight from zlib, bypassing the buffer.
+    fn read_raw(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let mut total = 0;
+        for chunk in out.chunks_mut(MAX_CHUNK) {
+            let n = match self.gzread(chunk) {
+                Ok(n) => n,
+                // Whatever was read already is returned first; the error
+                // comes back on the next call.
+                Err(_) if total > 0 => break,
+                Err(e) => return Err(e),
+            };
+            total += n;
+            if n < chunk.len() {
+                break;
+            }
+        }
+        Ok(total)
+    }
+
+    /// One call to `gzread`, with `out` no longer than `MAX_CHUNK`.
+    ///
+    /// zlib returns what it could decompress from a truncated file and only
+    /// records `Z_BUF_ERROR`, which newer versions then clear on the next
+    /// call. So the truncation is noted as soon as it shows up, and reported
+    /// once the data runs out, as the `io` decoders do.
+    fn gzread(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let n = unsafe { gzread(self.file, out.as_mut_ptr().cast(), out.len() as c_uint) };
+        if n < 0 {
+            return Err(self.error());
+        }
+        let mut errnum = Z_OK;
+        unsafe { gzerror(self.file, &mut errnum) };
+        self.truncated |= errnum == Z_BUF_ERROR;
+        if n == 0 && self.truncated && !out.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "gzip file ended early",
+            ));
+        }
+        Ok(n as usize)
+    }
+}
+
+impl Read for GzFile {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        // Skip the buffer entirely for large reads once it's empty.
+        if self.pos == self.cap && out.len() >= self.buf.len() {
+            return self.read_raw(out);
+        }
+        let n = {
+            let available = self.fill_buf()?;
+            let n = available.len().min(out.len());
+            out[..n].copy_from_slice(&available[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for GzFile {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.cap {
+            let mut buf = std::mem::take(&mut self.buf);
+            let read = self.gzread(&mut buf);
+            self.buf = buf;
+            self.pos = 0;
+            self.cap = read?;
+        }
+        Ok(&self.buf[self.pos..self.cap])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.cap);
+    }
+}
+
+impl Write for GzFile {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        for chunk in data.chunks(MAX_CHUNK) {
+            let n = unsafe { gzwrite(self.file, chunk.as_ptr().cast(), chunk.len() as c_uint) };
+            // `gzwrite` either takes everything or fails.
+            if n <= 0 {
+                return Err(self.error());
+            }
+        }
+        Ok(data.len())
+    }
+
+    /// Flushes with `Z_SYNC_FLUSH`, so everything written so far can be
+    /// decompressed from the file. Flushing often hurts compression.
+    fn flush(&mut self) -> io::Result<()> {
+        match unsafe { gzflush(self.file, Z_SYNC_FLUSH) } {
+            Z_OK => Ok(()),
+            _ => Err(self.error()),
+        }
+    }
+}
+
+/// Writes formatted text to `file`, like `gzprintf`, returning the number of
+/// uncompressed bytes written.
+///
+/// The text is formatted into a buffer kept in `file` and reused between
+/// calls, then handed to `gzwrite` in one piece, so there's no limit on its
+/// length as there is with `gzprintf`. Errors come from `gzerror`.
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use libz_sys::safe::{gz_write_fmt, GzFile};
+///
+/// let mut file = GzFile::open("log.gz", "wb")?;
+/// gz_write_fmt(&mut file, format_args!("{} + {} = {}\n", 1, 2, 1 + 2))?;
+/// file.close()
+/// # }
+/// ```
+pub fn gz_write_fmt(file: &mut GzFile, args: fmt::Arguments<'_>) -> io::Result<usize> {
+    let mut buf = std::mem::take(&mut file.fmt_buf);
+    buf.clear();
+    let ret = match fmt::Write::write_fmt(&mut buf, args) {
+        Ok(()) => file.write_all(buf.as_bytes()).map(|()| buf.len()),
+        Err(_) => Err(io::Error::other(
+            "a formatting trait implementation returned an error",
+        )),
+    };
+    if buf.capacity() <= MAX_RETAINED_FMT {
+        file.fmt_buf = buf;
+    }
+    ret
+}
+
+/// The [`fmt::Write`] adapter returned by [`GzFile::fmt_writer`]. It's a
+/// separate type because `GzFile` implementing both `Write` traits would make
+/// `write!` ambiguous wherever both are in scope.
+struct FmtWriter<'a> {
+    file: &'a mut GzFile,
+}
+
+impl fmt::Write for FmtWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.file.write_all(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+
+    /// Formats everything up front, so the text reaches `gzwrite` in a single
+    /// call rather than one call per piece.
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> fmt::Result {
+        gz_write_fmt(self.file, args)
+            .map(drop)
+            .map_err(|_| fmt::Error)
+    }
+}
+
+impl Seek for GzFile {
+    /// Seeks with `gzseek`, which positions in the uncompressed data.
+    ///
+    /// Seeking relative to the end isn't supported by zlib. Seeking
+    /// backwards in a file opened for reading rewinds and decompresses from
+    /// the start, and seeking forwards in a file opened for writing writes
+    /// zeros.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (offset, whence) = match pos {
+            SeekFrom::Start(offset) => (i64::try_from(offset).ok(), libc::SEEK_SET),
+            // zlib's position is past whatever is still buffered here.
+            SeekFrom::Current(offset) => (
+                offset.checked_sub((self.cap - self.pos) as i64),
+                libc::SEEK_CUR,
+            ),
+            SeekFrom::End(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "gzseek does not support seeking from the end",
+                ))
+            }
+        };
+        let offset = offset
+            .and_then(|offset| z_off_t::try_from(offset).ok())
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "seek offset out of range")
+            })?;
+        let ret = unsafe { gzseek(self.file, offset, whence) };
+        if ret < 0 {
+            return Err(self.error());
+        }
+        self.pos = 0;
+        self.cap = 0;
+        self.truncated = false;
+        Ok(ret as u64)
+    }
+}
+
+impl Drop for GzFile {
+    fn drop(&mut self) {
+        if !self.file.is_null() {
+            unsafe {
+                gzclose(self.file);
+            }
+        }
+    }
+}
+
+// Windows paths are UTF-16, which `gzopen_w` takes as-is.
+#[cfg(all(windows, any(zng, zlib_1_2_7_1)))]
+fn open_path(path: &Path, mode: &CStr) -> io::Result<gzFile> {
+    use std::os::windows::ffi::OsStrExt;
+    let mut wide = path.as_os_str().encode_wide().collect::<Vec<u16>>();
+    if wide.contains(&0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "string contains a NUL byte",
+        ));
+    }
+    wide.push(0);
+    Ok(unsafe { gzopen_w(wide.as_ptr(), mode.as_ptr()) })
+}
+
+#[cfg(not(all(windows, any(zng, zlib_1_2_7_1))))]
+fn open_path(path: &Path, mode: &CStr) -> io::Result<gzFile> {
+    let path = path_to_cstring(path)?;
+    Ok(unsafe { gzopen(path.as_ptr(), mode.as_ptr()) })
+}
+
+#[cfg(unix)]
+fn path_to_cstring(path: &Path) -> io::Result<CString> {
+    use std::os::unix::ffi::OsStrExt;
+    to_cstring(path.as_os_str().as_bytes())
+}
+
+// `gzopen` takes a narrow path, so anything that isn't UTF-8 is rejected
+// rather than being mangled. Only zlib older than `gzopen_w` gets here on
+// Windows.
+#[cfg(all(not(unix), not(all(windows, any(zng, zlib_1_2_7_1)))))]
+fn path_to_cstring(path: &Path) -> io::Result<CString> {
+    match path.to_str() {
+        Some(path) => to_cstring(path.as_bytes()),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "path is not valid UTF-8",
+        )),
+    }
+}
+
+fn mode_to_cstring(mode: &str) -> io::Result<CString> {
+    to_cstring(mode.as_bytes())
+}
+
+fn to_cstring(bytes: &[u8]) -> io::Result<CString> {
+    CString::new(bytes)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "string contains a NUL byte"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::safe::GzDecoder;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// A path in the temporary directory that is removed on drop.
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> TempPath {
+            let name = format!("libz-sys-{}-{}.gz", std::process::id(), name);
+            TempPath(std::env::temp_dir().join(name))
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i % 251) as u8 ^ (i / 1024) as u8)
+            .collect()
+    }
+
+    fn write_file(path: &Path, data: &[u8]) {
+        let mut file = GzFile::open(path, "wb").unwrap();
+        file.write_all(data).unwrap();
+        file.close().unwrap();
+    }
+
+    #[test]
+    fn write_then_read() {
+        let path = TempPath::new("write_then_read");
+        let data = sample(100_000);
+        write_file(&path.0, &data);
+
+        let mut file = GzFile::open(&path.0, "rb").unwrap();
+        let mut out = Vec::new();
+        file.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+
+        // The file is plain gzip.
+        let compressed = fs::read(&path.0).unwrap();
+        let mut out = Vec::new();
+        GzDecoder::new(&compressed[..])
+            .unwrap()
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn buffered_lines() {
+        let path = TempPath::new("buffered_lines");
+        write_file(&path.0, b"one\ntwo\nthree\n");
+        let file = GzFile::open(&path.0, "rb").unwrap();
+        let lines = file.lines().collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(lines, ["one", "two", "three"]);
+    }
+
+    #[test]
+    fn seek_while_reading() {
+        let path = TempPath::new("seek_while_reading");
+        let data = sample(50_000);
+        write_file(&path.0, &data);
+
+        let mut file = GzFile::open(&path.0, "rb").unwrap();
+        let mut buf = [0; 100];
+        file.read_exact(&mut buf).unwrap();
+        // Relative seeks account for what is still buffered.
+        assert_eq!(file.seek(SeekFrom::Current(1_000)).unwrap(), 1_100);
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[1_100..1_200]);
+
+        assert_eq!(file.seek(SeekFrom::Start(10)).unwrap(), 10);
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[10..110]);
+
+        let err = file.seek(SeekFrom::End(0)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn seek_while_writing_fills_with_zeros() {
+        let path = TempPath::new("seek_while_writing");
+        let mut file = GzFile::open(&path.0, "wb").unwrap();
+        file.write_all(b"ab").unwrap();
+        assert_eq!(file.seek(SeekFrom::Start(5)).unwrap(), 5);
+        file.write_all(b"cd").unwrap();
+        // Writes can't go backwards.
+        assert!(file.seek(SeekFrom::Start(0)).is_err());
+        file.close().unwrap();
+
+        let mut out = Vec::new();
+        GzFile::open(&path.0, "rb")
+            .unwrap()
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, b"ab\0\0\0cd");
+    }
+
+    #[test]
+    fn wrong_mode_is_an_error() {
+        let path = TempPath::new("wrong_mode");
+        let mut file = GzFile::open(&path.0, "wb").unwrap();
+        let mut buf = [0; 4];
+        assert!(file.read(&mut buf).is_err());
+    }
+
+    #[test]
+    fn open_missing_file() {
+        let path = TempPath::new("missing");
+        let err = GzFile::open(&path.0, "rb").err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(GzFile::open(&path.0, "r\0b").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_fd() {
+        let path = TempPath::new("from_fd");
+        write_file(&path.0, b"through a descriptor");
+        let fd = OwnedFd::from(fs::File::open(&path.0).unwrap());
+        let mut file = GzFile::from_fd(fd, "rb").unwrap();
+        let mut out = String::new();
+        file.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "through a descriptor");
+    }
+
+    #[test]
+    fn formatted_output() {
+        use std::fmt::Write as _;
+
+        let path = TempPath::new("formatted_output");
+        let mut file = GzFile::open(&path.0, "wb").unwrap();
+        let n = gz_write_fmt(&mut file, format_args!("{} + {} = {}\n", 1, 2, 1 + 2)).unwrap();
+        assert_eq!(n, 10);
+        // With both `Write` traits in scope, `write!` works on the file and
+        // on the adapter alike.
+        writeln!(file, "io {}", 1).unwrap();
+        writeln!(file.fmt_writer(), "fmt {}", 2).unwrap();
+        file.fmt_writer().write_str("str\n").unwrap();
+        let long = "x".repeat(MAX_RETAINED_FMT + 1);
+        gz_write_fmt(&mut file, format_args!("{}\n", long)).unwrap();
+        assert!(file.fmt_buf.capacity() <= MAX_RETAINED_FMT);
+        assert!(file.last_error().is_none());
+        file.close().unwrap();
+
+        let mut out = String::new();
+        GzFile::open(&path.0, "rb")
+            .unwrap()
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(out, format!("1 + 2 = 3\nio 1\nfmt 2\nstr\n{}\n", long));
+    }
+
+    #[test]
+    fn formatting_into_a_file_open_for_reading_fails() {
+        use std::fmt::Write as _;
+
+        let path = TempPath::new("formatting_read_mode");
+        write_file(&path.0, b"data");
+        let mut file = GzFile::open(&path.0, "rb").unwrap();
+        assert!(gz_write_fmt(&mut file, format_args!("{}", 1)).is_err());
+        assert_eq!(write!(file.fmt_writer(), "{}", 1), Err(fmt::Error));
+    }
+
+    #[test]
+    fn truncated_file_is_an_error() {
+        let path = TempPath::new("truncated");
+        let data = sample(100_000);
+        write_file(&path.0, &data);
+        let compressed = fs::read(&path.0).unwrap();
+        fs::write(&path.0, &compressed[..compressed.len() / 2]).unwrap();
+
+        // Large reads go straight to `gzread`.
+        let mut file = GzFile::open(&path.0, "rb").unwrap();
+        assert!(file.last_error().is_none());
+        let mut out = Vec::new();
+        let err = file.read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(out.len() < data.len());
+        assert_eq!(out[..], data[..out.len()]);
+
+        // Small ones go through the buffer.
+        let mut file = GzFile::open(&path.0, "rb").unwrap();
+        let mut byte = [0; 1];
+        let err = loop {
+            match file.read(&mut byte) {
+                Ok(1) => {}
+                Ok(n) => panic!("read {} bytes", n),
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // Rewinding starts over.
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], data[0]);
+    }
+}
@@ -0,0 +1,459 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::os::raw::c_int;
+use std::ptr;
+
+use super::ZError;
+use crate::*;
+
+/// A gzip header, as written by `deflateSetHeader` or read by
+/// `inflateGetHeader`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GzHeader {
+    /// Modification time in seconds since the Unix epoch, or 0 if unknown.
+    pub mtime: u32,
+    /// The operating system the data was compressed on. 255 means unknown.
+    pub os: u8,
+    /// FTEXT: the data is probably text.
+    pub text: bool,
+    /// FHCRC: the header is followed by a CRC-16 of itself.
+    pub hcrc: bool,
+    /// FNAME: the original file name, without the terminating NUL.
+    pub name: Option<Vec<u8>>,
+    /// FCOMMENT: a comment, without the terminating NUL.
+    pub comment: Option<Vec<u8>>,
+    /// FEXTRA: the extra field, split into its subfields.
+    pub extra: Option<Vec<ExtraSubfield>>,
+}
+
+impl Default for GzHeader {
+    fn default() -> GzHeader {
+        GzHeader {
+            mtime: 0,
+            os: 255,
+            text: false,
+            hcrc: false,
+            name: None,
+            comment: None,
+            extra: None,
+        }
+    }
+}
+
+/// One subfield of a gzip FEXTRA field.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ExtraSubfield {
+    /// The first subfield ID byte, SI1.
+    pub si1: u8,
+    /// The second subfield ID byte, SI2.
+    pub si2: u8,
+    /// The subfield's contents.
+    pub data: Vec<u8>,
+}
+
+/// A gzip header that didn't fit in the space set aside for it, or whose
+/// extra field couldn't be split into subfields.
+///
+/// The header is still available, with any overlong field cut short and only
+/// the complete subfields of the extra field.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HeaderError {
+    header: GzHeader,
+    name_truncated: bool,
+    comment_truncated: bool,
+    extra_truncated: bool,
+    extra_malformed: bool,
+}
+
+impl HeaderError {
+    /// As much of the header as could be recovered.
+    pub fn header(&self) -> &GzHeader {
+        &self.header
+    }
+
+    /// Whether the name was longer than its capacity.
+    pub fn name_truncated(&self) -> bool {
+        self.name_truncated
+    }
+
+    /// Whether the comment was longer than its capacity.
+    pub fn comment_truncated(&self) -> bool {
+        self.comment_truncated
+    }
+
+    /// Whether the extra field was longer than its capacity.
+    pub fn extra_truncated(&self) -> bool {
+        self.extra_truncated
+    }
+
+    /// Whether the extra field wasn't a valid sequence of subfields.
+    pub fn extra_malformed(&self) -> bool {
+        self.extra_malformed
+    }
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let problems = [
+            (self.name_truncated, "name truncated"),
+            (self.comment_truncated, "comment truncated"),
+            (self.extra_truncated, "extra field truncated"),
+            (self.extra_malformed, "extra field malformed"),
+        ];
+        f.write_str("gzip header")?;
+        let mut sep = ": ";
+        for (_, problem) in problems.iter().filter(|(hit, _)| *hit) {
+            write!(f, "{}{}", sep, problem)?;
+            sep = ", ";
+        }
+        Ok(())
+    }
+}
+
+impl Error for HeaderError {}
+
+/// A `gz_header` together with the buffers its pointers refer to.
+///
+/// This is always boxed and owned by the stream it was registered with, so
+/// neither the struct nor the buffers move or go away while zlib may use
+/// them.
+pub(crate) struct HeaderSlot {
+    raw: gz_header,
+    name: Vec<u8>,
+    comment: Vec<u8>,
+    extra: Vec<u8>,
+}
+
+impl HeaderSlot {
+    /// A slot describing `header`, for `deflateSetHeader`.
+    pub(crate) fn for_deflate(header: &GzHeader) -> Result<Box<HeaderSlot>, ZError> {
+        let nul_terminated = |field: &Option<Vec<u8>>, what: &str| match field {
+            Some(bytes) if bytes.contains(&0) => Err(ZError::with_message(
+                Z_STREAM_ERROR,
+                format!("gzip header {} contains a NUL byte", what),
+            )),
+            Some(bytes) => {
+                let mut buf = bytes.clone();
+                buf.push(0);
+                Ok(buf)
+            }
+            None => Ok(Vec::new()),
+        };
+        let name = nul_terminated(&header.name, "name")?;
+        let comment = nul_terminated(&header.comment, "comment")?;
+
+        let mut extra = Vec::new();
+        for field in header.extra.iter().flatten() {
+            let len = u16::try_from(field.data.len()).map_err(|_| extra_too_long())?;
+            extra.extend_from_slice(&[field.si1, field.si2]);
+            extra.extend_from_slice(&len.to_le_bytes());
+            extra.extend_from_slice(&field.data);
+        }
+        if extra.len() > u16::MAX as usize {
+            return Err(extra_too_long());
+        }
+
+        let mut slot = Box::new(HeaderSlot {
+            raw: empty_header(),
+            name,
+            comment,
+            extra,
+        });
+        slot.raw.text = header.text as c_int;
+        slot.raw.time = header.mtime.into();
+        slot.raw.os = header.os.into();
+        slot.raw.hcrc = header.hcrc as c_int;
+        if header.name.is_some() {
+            slot.raw.name = slot.name.as_mut_ptr();
+        }
+        if header.comment.is_some() {
+            slot.raw.comment = slot.comment.as_mut_ptr();
+        }
+        if header.extra.is_some() {
+            slot.raw.extra = slot.extra.as_mut_ptr();
+            slot.raw.extra_len = slot.extra.len() as uInt;
+        }
+        Ok(slot)
+    }
+
+    /// A slot with room for a name, comment and extra field of the given
+    /// sizes, for `inflateGetHeader`.
+    ///
+    /// The name and comment get an extra byte for the terminating NUL, so a
+    /// field of exactly its maximum length still fits.
+    pub(crate) fn for_inflate(
+        name_max: usize,
+        comment_max: usize,
+        extra_max: usize,
+    ) -> Result<Box<HeaderSlot>, ZError> {
+        let too_large =
+            || ZError::with_message(Z_STREAM_ERROR, "gzip header capacity is too large");
+        let cap = |len: usize| uInt::try_from(len).map_err(|_| too_large());
+        let name_len = name_max.checked_add(1).ok_or_else(too_large)?;
+        let comment_len = comment_max.checked_add(1).ok_or_else(too_large)?;
+        let (name_cap, comment_cap, extra_cap) =
+            (cap(name_len)?, cap(comment_len)?, cap(extra_max)?);
+        let mut slot = Box::new(HeaderSlot {
+            raw: empty_header(),
+            name: vec![0; name_len],
+            comment: vec![0; comment_len],
+            extra: vec![0; extra_max],
+        });
+        slot.raw.name = slot.name.as_mut_ptr();
+        slot.raw.name_max = name_cap;
+        slot.raw.comment = slot.comment.as_mut_ptr();
+        slot.raw.comm_max = comment_cap;
+        slot.raw.extra = slot.extra.as_mut_ptr();
+        slot.raw.extra_max = extra_cap;
+        Ok(slot)
+    }
+
+    pub(crate) fn as_raw(&mut self) -> gz_headerp {
+        &mut self.raw
+    }
+
+    /// The header zlib filled in, once it has read all of it.
+    ///
+    /// A stream that turned out not to be gzip reports a `done` of -1 and
+    /// yields nothing. zlib nulls the pointer of any field the header didn't
+    /// have.
+    pub(crate) fn parsed(&self) -> Option<Result<GzHeader, HeaderError>> {
+        if self.raw.done != 1 {
+            return None;
+        }
+        let raw = &self.raw;
+        // zlib stops storing a NUL-terminated field once it fills the buffer,
+        // so a missing NUL means the field was cut short. The buffer has a
+        // byte more than the caller asked for, which only the NUL may use.
+        let string = |present: bool, buf: &[u8]| -> (Option<Vec<u8>>, bool) {
+            if !present {
+                return (None, false);
+            }
+            match buf.iter().position(|&b| b == 0) {
+                Some(end) => (Some(buf[..end].to_vec()), false),
+                None => (Some(buf[..buf.len() - 1].to_vec()), true),
+            }
+        };
+        let (name, name_truncated) = string(!raw.name.is_null(), &self.name);
+        let (comment, comment_truncated) = string(!raw.comment.is_null(), &self.comment);
+
+        // `extra_len` is the real length even when less than that was stored.
+        let (extra, extra_truncated, extra_malformed) = if raw.extra.is_null() {
+            (None, false, false)
+        } else {
+            let len = raw.extra_len as usize;
+            let truncated = len > self.extra.len();
+            let (fields, malformed) = parse_subfields(&self.extra[..len.min(self.extra.len())]);
+            (Some(fields), truncated, malformed && !truncated)
+        };
+
+        let header = GzHeader {
+            mtime: raw.time as u32,
+            os: raw.os as u8,
+            text: raw.text != 0,
+            hcrc: raw.hcrc != 0,
+            name,
+            comment,
+            extra,
+        };
+        if name_truncated || comment_truncated || extra_truncated || extra_malformed {
+            Some(Err(HeaderError {
+                header,
+                name_truncated,
+                comment_truncated,
+                extra_truncated,
+                extra_malformed,
+            }))
+        } else {
+            Some(Ok(header))
+        }
+    }
+}
+
+/// Splits an extra field into subfields, reporting whether there were bytes
+/// left over that didn't form a complete one.
+fn parse_subfields(mut extra: &[u8]) -> (Vec<ExtraSubfield>, bool) {
+    let mut fields = Vec::new();
+    while extra.len() >= 4 {
+        let len = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+        if extra.len() - 4 < len {
+            break;
+        }
+        fields.push(ExtraSubfield {
+            si1: extra[0],
+            si2: extra[1],
+            data: extra[4..4 + len].to_vec(),
+        });
+        extra = &extra[4 + len..];
+    }
+    (fields, !extra.is_empty())
+}
+
+fn extra_too_long() -> ZError {
+    ZError::with_message(
+        Z_STREAM_ERROR,
+        "gzip extra field is longer than 65535 bytes",
+    )
+}
+
+fn empty_header() -> gz_header {
+    gz_header {
+        text: 0,
+        time: 0,
+        xflags: 0,
+        os: 0,
+        extra: ptr::null_mut(),
+        extra_len: 0,
+        extra_max: 0,
+        name: ptr::null_mut(),
+        name_max: 0,
+        comment: ptr::null_mut(),
+        comm_max: 0,
+        hcrc: 0,
+        done: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::safe::{
+        Deflate, DeflateParams, ErrorCode, Flush, Format, Inflate, InflateParams, Status,
+    };
+
+    fn header() -> GzHeader {
+        GzHeader {
+            mtime: 1_234_567_890,
+            os: 3,
+            text: true,
+            hcrc: true,
+            name: Some(b"file.txt".to_vec()),
+            comment: Some(b"a comment".to_vec()),
+            extra: Some(vec![
+                ExtraSubfield {
+                    si1: b'A',
+                    si2: b'B',
+                    data: b"xyz".to_vec(),
+                },
+                ExtraSubfield {
+                    si1: b'C',
+                    si2: b'D',
+                    data: Vec::new(),
+                },
+            ]),
+        }
+    }
+
+    fn gzip_with(header: &GzHeader) -> Vec<u8> {
+        let params = DeflateParams::new().format(Format::Gzip);
+        let mut deflate = Deflate::with_params(&params).unwrap();
+        deflate.set_header(header).unwrap();
+        let mut out = vec![0; 1024];
+        let (_, len) = deflate.compress(b"data", &mut out, Flush::Finish).unwrap();
+        assert!(deflate.is_finished());
+        out.truncate(len);
+        out
+    }
+
+    fn read_header(
+        gzip: &[u8],
+        name_max: usize,
+        comment_max: usize,
+        extra_max: usize,
+    ) -> Result<GzHeader, HeaderError> {
+        let params = InflateParams::new().format(Format::Auto);
+        let mut inflate = Inflate::with_params(&params).unwrap();
+        inflate
+            .capture_header(name_max, comment_max, extra_max)
+            .unwrap();
+        let mut out = [0; 64];
+        let (status, _, produced) = inflate.decompress(gzip, &mut out, Flush::None).unwrap();
+        assert_eq!(status, Status::StreamEnd);
+        assert_eq!(&out[..produced], b"data");
+        inflate.header().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let header = header();
+        assert_eq!(read_header(&gzip_with(&header), 64, 64, 64), Ok(header));
+        let empty = GzHeader::default();
+        assert_eq!(read_header(&gzip_with(&empty), 64, 64, 64), Ok(empty));
+    }
+
+    #[test]
+    fn fields_of_exactly_the_maximum_length() {
+        let header = header();
+        let gzip = gzip_with(&header);
+        assert_eq!(read_header(&gzip, 8, 9, 11), Ok(header));
+    }
+
+    #[test]
+    fn truncated_fields() {
+        let gzip = gzip_with(&header());
+        let err = read_header(&gzip, 7, 9, 11).unwrap_err();
+        assert!(err.name_truncated());
+        assert!(!err.comment_truncated() && !err.extra_truncated());
+        assert_eq!(err.header().name.as_deref(), Some(&b"file.tx"[..]));
+        assert_eq!(err.to_string(), "gzip header: name truncated");
+
+        let err = read_header(&gzip, 0, 2, 7).unwrap_err();
+        assert!(err.name_truncated() && err.comment_truncated() && err.extra_truncated());
+        assert!(!err.extra_malformed());
+        let header = err.header();
+        assert_eq!(header.name.as_deref(), Some(&b""[..]));
+        assert_eq!(header.comment.as_deref(), Some(&b"a "[..]));
+        // Only the first subfield fits.
+        assert_eq!(header.extra.as_ref().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn malformed_extra() {
+        let mut header = header();
+        header.hcrc = false;
+        header.extra = Some(Vec::new());
+        let mut gzip = gzip_with(&header);
+        // Swap the empty extra field, whose XLEN follows the 10 fixed bytes,
+        // for two bytes that can't be a subfield.
+        assert_eq!(&gzip[10..12], &[0, 0]);
+        gzip.splice(10..12, [2, 0, b'A', b'B']);
+        let err = read_header(&gzip, 64, 64, 64).unwrap_err();
+        assert!(err.extra_malformed() && !err.extra_truncated());
+        assert_eq!(err.header().extra, Some(Vec::new()));
+    }
+
+    #[test]
+    fn invalid_headers_are_rejected() {
+        let mut header = header();
+        header.name = Some(b"nul\0inside".to_vec());
+        let err = HeaderSlot::for_deflate(&header).err().unwrap();
+        assert_eq!(err.code(), ErrorCode::Stream);
+
+        let header = GzHeader {
+            extra: Some(vec![ExtraSubfield {
+                si1: 0,
+                si2: 0,
+                data: vec![0; 70_000],
+            }]),
+            ..GzHeader::default()
+        };
+        let err = HeaderSlot::for_deflate(&header).err().unwrap();
+        assert_eq!(err.code(), ErrorCode::Stream);
+    }
+
+    #[test]
+    fn not_gzip() {
+        let mut deflate = Deflate::with_params(&DeflateParams::new()).unwrap();
+        let mut zlib = vec![0; 64];
+        let (_, len) = deflate.compress(b"data", &mut zlib, Flush::Finish).unwrap();
+        let params = InflateParams::new().format(Format::Auto);
+        let mut inflate = Inflate::with_params(&params).unwrap();
+        inflate.capture_header(64, 64, 64).unwrap();
+        let mut out = [0; 64];
+        inflate
+            .decompress(&zlib[..len], &mut out, Flush::None)
+            .unwrap();
+        assert_eq!(inflate.header(), None);
+    }
+}
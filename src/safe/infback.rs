@@ -0,0 +1,297 @@
+use std::any::Any;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::raw::{c_int, c_uchar, c_uint, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use super::{new_stream, WindowBits, ZError};
+use crate::*;
+
+// How much input is requested from the source at a time.
+const INPUT_SIZE: usize = 32 * 1024;
+
+/// Raw deflate decompression through `inflateBack`.
+///
+/// `inflateBack` pulls input and pushes output through callbacks and
+/// decompresses straight into its window, which makes it the fastest way to
+/// inflate with stock zlib. This type owns the window and the input buffer,
+/// and calls `inflateBackEnd` when dropped. Only raw deflate data is
+/// supported, without a zlib or gzip wrapper.
+pub struct InflateBack {
+    stream: Box<z_stream>,
+    _window: Box<[u8]>,
+    input: Box<[u8]>,
+}
+
+unsafe impl Send for InflateBack {}
+unsafe impl Sync for InflateBack {}
+
+/// What the callbacks need, reached through `in_desc` and `out_desc`.
+struct Callbacks<'a, I, O> {
+    input: I,
+    output: O,
+    buf: &'a mut [u8],
+    eof: bool,
+    error: Option<io::Error>,
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+impl InflateBack {
+    /// Creates a stream with `inflateBackInit_` and a window of
+    /// `2^window_bits` bytes, which must be at least as large as the window
+    /// the data was compressed with.
+    pub fn new(window_bits: WindowBits) -> Result<InflateBack, ZError> {
+        let mut stream = new_stream();
+        let mut window = vec![0; 1 << window_bits.as_raw()].into_boxed_slice();
+        let ret = unsafe {
+            inflateBackInit_(
+                &mut *stream,
+                window_bits.as_raw(),
+                window.as_mut_ptr(),
+                zlibVersion(),
+                mem::size_of::<z_stream>() as c_int,
+            )
+        };
+        if ret != Z_OK {
+            return Err(unsafe { ZError::from_stream(ret, &stream) });
+        }
+        Ok(InflateBack {
+            stream,
+            _window: window,
+            input: vec![0; INPUT_SIZE].into_boxed_slice(),
+        })
+    }
+
+    /// Decompresses one deflate stream from `reader` into `writer`.
+    pub fn run<R: Read, W: Write>(&mut self, mut reader: R, mut writer: W) -> io::Result<()> {
+        self.run_with(|buf| reader.read(buf), |data| writer.write_all(data))
+    }
+
+    /// Decompresses one deflate stream, pulling input from `input` and
+    /// pushing output to `output`.
+    ///
+    /// `input` fills the buffer it is given and returns how many bytes it
+    /// wrote, with 0 meaning the end of the input, just like [`Read::read`].
+    /// `output` receives each run of decompressed bytes in order.
+    ///
+    /// Errors from either closure stop decompression and are returned as-is.
+    /// A panic in either is caught before it reaches zlib, and resumed once
+    /// `inflateBack` has returned.
+    pub fn run_with<I, O>(&mut self, input: I, output: O) -> io::Result<()>
+    where
+        I: FnMut(&mut [u8]) -> io::Result<usize>,
+        O: FnMut(&[u8]) -> io::Result<()>,
+    {
+        let mut callbacks = Callbacks {
+            input,
+            output,
+            buf: &mut self.input,
+            eof: false,
+            error: None,
+            panic: None,
+        };
+        let desc = &mut callbacks as *mut Callbacks<'_, I, O> as *mut c_void;
+        self.stream.next_in = ptr::null_mut();
+        self.stream.avail_in = 0;
+        let ret = unsafe { inflateBack(&mut *self.stream, pull::<I, O>, desc, push::<I, O>, desc) };
+
+        if let Some(payload) = callbacks.panic {
+            panic::resume_unwind(payload);
+        }
+        match ret {
+            Z_STREAM_END => Ok(()),
+            // One of the callbacks gave up.
+            Z_BUF_ERROR => Err(callbacks.error.unwrap_or_else(|| {
+                io::Error::new(io::ErrorKind::UnexpectedEof, "deflate stream ended early")
+            })),
+            ret => Err(unsafe { ZError::from_stream(ret, &self.stream) }.into()),
+        }
+    }
+
+    /// Input that was read past the end of the deflate stream by the last
+    /// call to [`InflateBack::run`] or [`InflateBack::run_with`].
+    pub fn remaining_input(&self) -> &[u8] {
+        if self.stream.next_in.is_null() {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.stream.next_in, self.stream.avail_in as usize) }
+    }
+}
+
+impl Drop for InflateBack {
+    fn drop(&mut self) {
+        unsafe {
+            inflateBackEnd(&mut *self.stream);
+        }
+    }
+}
+
+/// The `in_func`: returns the number of bytes made available at `*buf`, with
+/// 0 telling zlib to stop.
+unsafe extern "C" fn pull<I, O>(desc: *mut c_void, buf: *mut *const c_uchar) -> c_uint
+where
+    I: FnMut(&mut [u8]) -> io::Result<usize>,
+{
+    let callbacks = &mut *(desc as *mut Callbacks<'_, I, O>);
+    if callbacks.eof {
+        return 0;
+    }
+    let result = panic::catch_unwind(AssertUnwindSafe(|| loop {
+        match (callbacks.input)(callbacks.buf) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            res => return res,
+        }
+    }));
+    match result {
+        Ok(Ok(n)) => {
+            *buf = callbacks.buf.as_ptr();
+            callbacks.eof = n == 0;
+            n.min(callbacks.buf.len()) as c_uint
+        }
+        Ok(Err(e)) => {
+            callbacks.error = Some(e);
+            0
+        }
+        Err(payload) => {
+            callbacks.panic = Some(payload);
+            0
+        }
+    }
+}
+
+/// The `out_func`: returns nonzero to tell zlib to stop.
+unsafe extern "C" fn push<I, O>(desc: *mut c_void, data: *mut c_uchar, len: c_uint) -> c_int
+where
+    O: FnMut(&[u8]) -> io::Result<()>,
+{
+    let callbacks = &mut *(desc as *mut Callbacks<'_, I, O>);
+    let data = std::slice::from_raw_parts(data, len as usize);
+    match panic::catch_unwind(AssertUnwindSafe(|| (callbacks.output)(data))) {
+        Ok(Ok(())) => 0,
+        Ok(Err(e)) => {
+            callbacks.error = Some(e);
+            1
+        }
+        Err(payload) => {
+            callbacks.panic = Some(payload);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::safe::{Deflate, DeflateParams, Flush, Format};
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i % 251) as u8 ^ (i / 1024) as u8)
+            .collect()
+    }
+
+    fn raw_deflate(input: &[u8]) -> Vec<u8> {
+        let params = DeflateParams::new().format(Format::Raw);
+        let mut deflate = Deflate::with_params(&params).unwrap();
+        let mut out = vec![0; input.len() + 1024];
+        let (_, len) = deflate.compress(input, &mut out, Flush::Finish).unwrap();
+        assert!(deflate.is_finished());
+        out.truncate(len);
+        out
+    }
+
+    #[test]
+    fn round_trip() {
+        let input = sample(200_000);
+        let compressed = raw_deflate(&input);
+        let mut back = InflateBack::new(WindowBits::MAX).unwrap();
+        for _ in 0..2 {
+            let mut out = Vec::new();
+            back.run(&compressed[..], &mut out).unwrap();
+            assert_eq!(out, input);
+        }
+    }
+
+    #[test]
+    fn remaining_input() {
+        let mut data = raw_deflate(b"hello");
+        data.extend_from_slice(b"trailer");
+        let mut back = InflateBack::new(WindowBits::MAX).unwrap();
+        let mut out = Vec::new();
+        back.run(&data[..], &mut out).unwrap();
+        assert_eq!(out, b"hello");
+        assert_eq!(back.remaining_input(), b"trailer");
+    }
+
+    #[test]
+    fn truncated_input() {
+        let compressed = raw_deflate(&sample(10_000));
+        let mut back = InflateBack::new(WindowBits::MAX).unwrap();
+        let err = back
+            .run(&compressed[..compressed.len() / 2], io::sink())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn invalid_data() {
+        let mut back = InflateBack::new(WindowBits::MAX).unwrap();
+        let err = back.run(&[0xff; 16][..], io::sink()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn callback_errors_are_returned() {
+        let compressed = raw_deflate(&sample(10_000));
+        let mut back = InflateBack::new(WindowBits::MAX).unwrap();
+        let err = back
+            .run_with(
+                |buf| {
+                    let n = compressed.len().min(buf.len());
+                    buf[..n].copy_from_slice(&compressed[..n]);
+                    Ok(n)
+                },
+                |_| Err(io::Error::new(io::ErrorKind::WriteZero, "full")),
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+
+        let err = back
+            .run_with(
+                |_| Err(io::Error::new(io::ErrorKind::ConnectionReset, "gone")),
+                |_| Ok(()),
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+    }
+
+    #[test]
+    fn panics_propagate() {
+        let compressed = raw_deflate(&sample(10_000));
+        let mut back = InflateBack::new(WindowBits::MAX).unwrap();
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| {
+            back.run_with(
+                |buf| {
+                    let n = compressed.len().min(buf.len());
+                    buf[..n].copy_from_slice(&compressed[..n]);
+                    Ok(n)
+                },
+                |_| panic!("output panicked"),
+            )
+        }))
+        .unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"output panicked"));
+
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| {
+            back.run_with(|_| panic!("input panicked"), |_| Ok(()))
+        }))
+        .unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"input panicked"));
+
+        // The stream is still usable afterwards.
+        let mut out = Vec::new();
+        back.run(&compressed[..], &mut out).unwrap();
+        assert_eq!(out, sample(10_000));
+    }
+}
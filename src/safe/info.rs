@@ -0,0 +1,692 @@
+use std::error::Error;
+use std::ffi::CStr;
+use std::fmt;
+use std::mem;
+
+use crate::*;
+
+/// The compile-time options of the linked library, decoded from
+/// `zlibCompileFlags`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CompileFlags {
+    bits: u64,
+}
+
+impl CompileFlags {
+    /// The flags of the library this crate is linked against.
+    // `unsigned long` is 32 bits on Windows.
+    #[allow(clippy::useless_conversion)]
+    pub fn get() -> CompileFlags {
+        CompileFlags::from_bits(u64::from(unsafe { zlibCompileFlags() }))
+    }
+
+    /// Decodes a value returned by `zlibCompileFlags`.
+    pub fn from_bits(bits: u64) -> CompileFlags {
+        CompileFlags { bits }
+    }
+
+    /// The undecoded value.
+    pub fn bits(self) -> u64 {
+        self.bits
+    }
+
+    /// The size of `uInt` in bytes, or `None` if it isn't 2, 4 or 8.
+    pub fn uint_size(self) -> Option<usize> {
+        self.size(0)
+    }
+
+    /// The size of `uLong` in bytes, or `None` if it isn't 2, 4 or 8.
+    pub fn ulong_size(self) -> Option<usize> {
+        self.size(2)
+    }
+
+    /// The size of a pointer in bytes, or `None` if it isn't 2, 4 or 8.
+    pub fn pointer_size(self) -> Option<usize> {
+        self.size(4)
+    }
+
+    /// The size of `z_off_t` in bytes, or `None` if it isn't 2, 4 or 8.
+    pub fn off_t_size(self) -> Option<usize> {
+        self.size(6)
+    }
+
+    /// `ZLIB_DEBUG`: built with debugging checks and tracing.
+    pub fn debug(self) -> bool {
+        self.bit(8)
+    }
+
+    /// `ASMV` or `ASMINF`: uses assembly code.
+    pub fn asm(self) -> bool {
+        self.bit(9)
+    }
+
+    /// `ZLIB_WINAPI`: exported functions use the WINAPI calling convention.
+    pub fn winapi(self) -> bool {
+        self.bit(10)
+    }
+
+    /// `BUILDFIXED`: the fixed inflate tables are built on first use, which
+    /// isn't thread-safe.
+    pub fn build_fixed(self) -> bool {
+        self.bit(12)
+    }
+
+    /// `DYNAMIC_CRC_TABLE`: the CRC tables are built on first use, which
+    /// isn't thread-safe.
+    pub fn dynamic_crc_table(self) -> bool {
+        self.bit(13)
+    }
+
+    /// `NO_GZCOMPRESS`: the gz* functions can't compress.
+    pub fn no_gzcompress(self) -> bool {
+        self.bit(16)
+    }
+
+    /// `NO_GZIP`: deflate can't write gzip streams, and inflate can't read
+    /// them.
+    pub fn no_gzip(self) -> bool {
+        self.bit(17)
+    }
+
+    /// `PKZIP_BUG_WORKAROUND`: inflate is slightly more permissive.
+    pub fn pkzip_bug_workaround(self) -> bool {
+        self.bit(20)
+    }
+
+    /// `FASTEST`: deflate only implements the lowest compression level.
+    pub fn fastest(self) -> bool {
+        self.bit(21)
+    }
+
+    /// `gzprintf` uses `sprintf` rather than `vsprintf`, which limits it to
+    /// 20 arguments after the format.
+    pub fn gzprintf_limited_args(self) -> bool {
+        self.bit(24)
+    }
+
+    /// `gzprintf` doesn't bound its output, so it isn't safe to use with
+    /// untrusted input.
+    pub fn gzprintf_unbounded(self) -> bool {
+        self.bit(25)
+    }
+
+    /// `gzprintf` infers the length of its output, as the `sprintf` variant
+    /// it uses returns nothing.
+    pub fn gzprintf_returns_void(self) -> bool {
+        self.bit(26)
+    }
+
+    /// `gzprintf` is missing and always returns an error.
+    pub fn no_gzprintf(self) -> bool {
+        self.bit(27)
+    }
+
+    fn bit(self, n: u32) -> bool {
+        self.bits & (1 << n) != 0
+    }
+
+    fn size(self, shift: u32) -> Option<usize> {
+        match (self.bits >> shift) & 0b11 {
+            0 => Some(2),
+            1 => Some(4),
+            2 => Some(8),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for CompileFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompileFlags")
+            .field("bits", &format_args!("{:#x}", self.bits))
+            .field("uint_size", &self.uint_size())
+            .field("ulong_size", &self.ulong_size())
+            .field("pointer_size", &self.pointer_size())
+            .field("off_t_size", &self.off_t_size())
+            .field("debug", &self.debug())
+            .field("asm", &self.asm())
+            .field("winapi", &self.winapi())
+            .field("build_fixed", &self.build_fixed())
+            .field("dynamic_crc_table", &self.dynamic_crc_table())
+            .field("no_gzcompress", &self.no_gzcompress())
+            .field("no_gzip", &self.no_gzip())
+            .field("pkzip_bug_workaround", &self.pkzip_bug_workaround())
+            .field("fastest", &self.fastest())
+            .field("gzprintf_limited_args", &self.gzprintf_limited_args())
+            .field("gzprintf_unbounded", &self.gzprintf_unbounded())
+            .field("gzprintf_returns_void", &self.gzprintf_returns_void())
+            .field("no_gzprintf", &self.no_gzprintf())
+            .finish()
+    }
+}
+
+/// Which zlib implementation the crate is using.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Implementation {
+    /// zlib itself, either the bundled copy or a system library.
+    StockZlib,
+    /// zlib-ng built with `ZLIB_COMPAT`, providing the zlib API.
+    ZlibNgCompat,
+    /// zlib-ng's own API, as used by `libz-ng-sys`.
+    ZlibNgNative,
+    /// A system library that doesn't identify itself as either, or whose
+    /// version contradicts how the crate was built.
+    Unknown,
+}
+
+/// What [`implementation`] found out about the linked library.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ImplementationInfo {
+    /// The kind of library.
+    pub implementation: Implementation,
+    /// The version reported by `zlibVersion`, such as `1.3.1`, or
+    /// `1.3.1.zlib-ng` for zlib-ng in compat mode. For zlib-ng in native mode
+    /// it's the zlib-ng version from `zlibng_version`.
+    pub version: String,
+    /// The version reported by `zlibng_version`, such as `2.2.4`, if the
+    /// library exports it. zlib-ng in native mode always does; in compat
+    /// mode it depends on the release, and the symbol can only be looked up
+    /// in a shared library on Linux, Android, FreeBSD and Apple platforms.
+    pub ng_version: Option<String>,
+}
+
+/// Identifies the zlib implementation in use.
+///
+/// This combines how the build script found the library with what it
+/// reports at runtime: zlib-ng in compat mode marks its `zlibVersion` with
+/// `zlib-ng`, and may also export `zlibng_version`, which is looked up where
+/// possible. Either catches a library found on the system, through
+/// pkg-config, vcpkg or otherwise, that is really zlib-ng. If the build
+/// script didn't run, as when the `links` key is overridden, the library is
+/// treated like a system one.
+pub fn implementation() -> ImplementationInfo {
+    // `zlibVersion` is bound to `zlibng_version` in native mode.
+    let version = unsafe { c_str(zlibVersion()) };
+
+    #[cfg(zng)]
+    let (implementation, ng_version) = (Implementation::ZlibNgNative, Some(version.clone()));
+    #[cfg(not(zng))]
+    let ng_version = compat_ng_version();
+    #[cfg(not(zng))]
+    let implementation = classify(
+        option_env!("LIBZ_SYS_SOURCE"),
+        &version,
+        ng_version.is_some(),
+    );
+
+    ImplementationInfo {
+        implementation,
+        version,
+        ng_version,
+    }
+}
+
+/// `source` is how the build script found the library, see `emit_source`.
+#[cfg(not(zng))]
+fn classify(source: Option<&str>, version: &str, exports_ng_version: bool) -> Implementation {
+    let is_ng = version.contains("zlib-ng") || exports_ng_version;
+    let is_zlib_1 = vernum(version).map(|n| n >> 12) == Some(1);
+    let from_system = match source {
+        Some("platform") | Some("vcpkg") | Some("pkg-config") | Some("smoke-test") | None => true,
+        Some(_) => false,
+    };
+    match source {
+        Some("zlib-ng-compat") if is_ng => Implementation::ZlibNgCompat,
+        Some("bundled-zlib") if is_zlib_1 && !is_ng => Implementation::StockZlib,
+        _ if from_system && is_ng => Implementation::ZlibNgCompat,
+        _ if from_system && is_zlib_1 => Implementation::StockZlib,
+        _ => Implementation::Unknown,
+    }
+}
+
+/// Looks up `zlibng_version` in the shared library that provides
+/// `zlibVersion`, and calls it if it's there.
+#[cfg(all(
+    not(zng),
+    feature = "libc",
+    any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_vendor = "apple"
+    )
+))]
+fn compat_ng_version() -> Option<String> {
+    use std::os::raw::{c_char, c_void};
+
+    unsafe {
+        let mut info: libc::Dl_info = mem::zeroed();
+        if libc::dladdr(zlibVersion as *const c_void, &mut info) == 0 || info.dli_fname.is_null() {
+            return None;
+        }
+        // `RTLD_NOLOAD` only hands out the library that's already mapped. It
+        // fails for the main program, so a statically linked zlib is left to
+        // its `zlibVersion`.
+        let handle = libc::dlopen(info.dli_fname, libc::RTLD_LAZY | libc::RTLD_NOLOAD);
+        if handle.is_null() {
+            return None;
+        }
+        let sym = libc::dlsym(handle, b"zlibng_version\0".as_ptr().cast());
+        let version = if sym.is_null() {
+            None
+        } else {
+            let zlibng_version: unsafe extern "C" fn() -> *const c_char = mem::transmute(sym);
+            Some(c_str(zlibng_version()))
+        };
+        libc::dlclose(handle);
+        version
+    }
+}
+
+#[cfg(all(
+    not(zng),
+    not(all(
+        feature = "libc",
+        any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd",
+            target_vendor = "apple"
+        )
+    ))
+))]
+fn compat_ng_version() -> Option<String> {
+    None
+}
+
+unsafe fn c_str(s: *const std::os::raw::c_char) -> String {
+    CStr::from_ptr(s).to_string_lossy().into_owned()
+}
+
+/// One way in which the library found at runtime differs from what the
+/// bindings were compiled for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AbiMismatch {
+    /// The library reports a version the bindings weren't built for: a
+    /// different major version, or one older than the newest API enabled at
+    /// build time.
+    Version {
+        /// The versions the bindings accept, such as `1.2.9 or later`.
+        expected: String,
+        /// What `zlibVersion` returned.
+        found: String,
+    },
+    /// A C type has a different size in the library, according to
+    /// `zlibCompileFlags`, than in the bindings.
+    TypeSize {
+        /// The C type, such as `uLong`.
+        name: &'static str,
+        /// The size of the Rust declaration.
+        expected: usize,
+        /// The size the library was built with, or `None` if it isn't one
+        /// `zlibCompileFlags` can express.
+        found: Option<usize>,
+    },
+    /// `inflateInit_` rejected the size of the Rust `z_stream`.
+    StreamSize {
+        /// The size of the Rust `z_stream`.
+        expected: usize,
+    },
+}
+
+impl fmt::Display for AbiMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AbiMismatch::Version { expected, found } => {
+                write!(f, "expected version {}, found {}", expected, found)
+            }
+            AbiMismatch::TypeSize {
+                name,
+                expected,
+                found: Some(found),
+            } => write!(f, "`{}` is {} bytes, expected {}", name, found, expected),
+            AbiMismatch::TypeSize {
+                name,
+                expected,
+                found: None,
+            } => write!(f, "`{}` has an unusual size, expected {}", name, expected),
+            AbiMismatch::StreamSize { expected } => {
+                write!(f, "`z_stream` is not {} bytes", expected)
+            }
+        }
+    }
+}
+
+/// Everything [`check_abi`] found wrong with the linked library.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AbiReport {
+    /// What `zlibVersion` returned.
+    pub version: String,
+    /// What `zlibCompileFlags` returned.
+    pub flags: CompileFlags,
+    /// The individual problems, never empty.
+    pub mismatches: Vec<AbiMismatch>,
+}
+
+impl fmt::Display for AbiReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "zlib {} (flags {:#x}) doesn't match the bindings: ",
+            self.version,
+            self.flags.bits()
+        )?;
+        for (i, mismatch) in self.mismatches.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for AbiReport {}
+
+/// Checks that the library found at runtime matches what the bindings were
+/// compiled for.
+///
+/// A mismatch usually means a different zlib was loaded than the one the
+/// crate was built against, for example through `LD_LIBRARY_PATH`. This
+/// compares:
+///
+/// - the version reported by `zlibVersion` with the API the build enabled,
+/// - the sizes of `uInt`, `uLong`, pointers and `z_off_t` reported by
+///   `zlibCompileFlags` with the Rust declarations,
+/// - the size of `z_stream`, by asking `inflateInit_` to check it, as
+///   zlib does for every stream (not possible with zlib-ng in native mode).
+pub fn check_abi() -> Result<(), AbiReport> {
+    let version = unsafe { c_str(zlibVersion()) };
+    let flags = CompileFlags::get();
+    let mut mismatches = Vec::new();
+
+    if let Some(expected) = version_mismatch(&version) {
+        mismatches.push(AbiMismatch::Version {
+            expected,
+            found: version.clone(),
+        });
+    }
+
+    let sizes = [
+        ("uInt", mem::size_of::<uInt>(), flags.uint_size()),
+        ("uLong", mem::size_of::<uLong>(), flags.ulong_size()),
+        ("voidpf", mem::size_of::<voidpf>(), flags.pointer_size()),
+        // With zlib-ng in native mode, the Rust `z_off_t` stands for the C
+        // `z_off64_t`, which the flags don't describe.
+        #[cfg(all(not(zng), feature = "libc"))]
+        ("z_off_t", mem::size_of::<z_off_t>(), flags.off_t_size()),
+    ];
+    for (name, expected, found) in sizes {
+        if found != Some(expected) {
+            mismatches.push(AbiMismatch::TypeSize {
+                name,
+                expected,
+                found,
+            });
+        }
+    }
+
+    if !stream_size_accepted() {
+        mismatches.push(AbiMismatch::StreamSize {
+            expected: mem::size_of::<z_stream>(),
+        });
+    }
+
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(AbiReport {
+            version,
+            flags,
+            mismatches,
+        })
+    }
+}
+
+/// Panics with the report from [`check_abi`] if the library found at runtime
+/// doesn't match the bindings.
+///
+/// Nothing calls this implicitly; programs that would rather fail loudly at
+/// startup than misbehave later can call it early in `main`.
+pub fn assert_abi() {
+    if let Err(report) = check_abi() {
+        panic!("{}", report);
+    }
+}
+
+/// Describes the accepted versions if `version` isn't one of them.
+#[cfg(not(zng))]
+fn version_mismatch(version: &str) -> Option<String> {
+    // The newest API enabled by the build script, which the library has to
+    // provide.
+    let required = [
+        (cfg!(zlib_1_2_12), 0x12c0, "1.2.12"),
+        (cfg!(zlib_1_2_9), 0x1290, "1.2.9"),
+        (cfg!(zlib_1_2_7_1), 0x1271, "1.2.7.1"),
+        (cfg!(zlib_1_2_5_2), 0x1252, "1.2.5.2"),
+        (cfg!(zlib_1_2_5_1), 0x1251, "1.2.5.1"),
+        (cfg!(zlib_1_2_3_5), 0x1235, "1.2.3.5"),
+    ]
+    .iter()
+    .find(|(enabled, _, _)| *enabled)
+    .map(|&(_, vernum, name)| (vernum, name));
+
+    // zlib itself only insists on the same major version.
+    let ok = match (vernum(version), required) {
+        (Some(found), Some((min, _))) => found >> 12 == 1 && found >= min,
+        (Some(found), None) => found >> 12 == 1,
+        (None, _) => false,
+    };
+    if ok {
+        None
+    } else {
+        Some(match required {
+            Some((_, name)) => format!("{} or later", name),
+            None => "1.x".to_string(),
+        })
+    }
+}
+
+/// Describes the accepted versions if `version` isn't one of them.
+#[cfg(zng)]
+fn version_mismatch(version: &str) -> Option<String> {
+    // The native API these bindings describe is the one of zlib-ng 2.
+    match vernum(version) {
+        Some(found) if found >> 12 == 2 => None,
+        _ => Some("2.x".to_string()),
+    }
+}
+
+/// Packs a version string such as `1.2.13` or `1.3.1.zlib-ng` the way
+/// `ZLIB_VERNUM` does, one nibble per component, saturating at 15.
+fn vernum(version: &str) -> Option<u32> {
+    let mut vernum = 0;
+    let mut parts = version.split('.');
+    for shift in [12, 8, 4, 0] {
+        let part = parts.next().unwrap_or("");
+        let end = part
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(part.len());
+        match part[..end].parse::<u32>() {
+            Ok(n) => vernum |= n.min(15) << shift,
+            // Only the major and minor versions are mandatory.
+            Err(_) if shift < 8 => break,
+            Err(_) => return None,
+        }
+    }
+    Some(vernum)
+}
+
+/// Whether `inflateInit_` accepts the Rust `z_stream`.
+#[cfg(not(zng))]
+fn stream_size_accepted() -> bool {
+    use super::blank_stream;
+    use std::os::raw::c_int;
+
+    let mut stream = blank_stream();
+    let ret = unsafe {
+        inflateInit_(
+            &mut stream,
+            zlibVersion(),
+            mem::size_of::<z_stream>() as c_int,
+        )
+    };
+    if ret == Z_OK {
+        unsafe { inflateEnd(&mut stream) };
+    }
+    ret != Z_VERSION_ERROR
+}
+
+/// zlib-ng's native API doesn't take the size of the stream, so there is
+/// nothing to check it against.
+#[cfg(zng)]
+fn stream_size_accepted() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_flags() {
+        // 4-byte `uInt`, 8-byte `uLong`, pointers and `z_off_t`, with
+        // `ZLIB_DEBUG`, `DYNAMIC_CRC_TABLE`, `NO_GZIP` and `FASTEST`.
+        let sizes = 0b01 | 0b10 << 2 | 0b10 << 4 | 0b10 << 6;
+        let flags = CompileFlags::from_bits(sizes | 1 << 8 | 1 << 13 | 1 << 17 | 1 << 21);
+        assert_eq!(flags.uint_size(), Some(4));
+        assert_eq!(flags.ulong_size(), Some(8));
+        assert_eq!(flags.pointer_size(), Some(8));
+        assert_eq!(flags.off_t_size(), Some(8));
+        assert!(flags.debug() && flags.dynamic_crc_table() && flags.no_gzip() && flags.fastest());
+        assert!(!flags.asm() && !flags.build_fixed() && !flags.no_gzcompress());
+
+        // A `uInt` size code of 3 means some other size; `uLong` is 2 bytes.
+        let odd = CompileFlags::from_bits(0b11);
+        assert_eq!(odd.uint_size(), None);
+        assert_eq!(odd.ulong_size(), Some(2));
+        assert!(format!("{:?}", odd).contains("bits: 0x3"));
+    }
+
+    #[test]
+    fn linked_library_sizes_match() {
+        let flags = CompileFlags::get();
+        assert_eq!(flags.uint_size(), Some(mem::size_of::<uInt>()));
+        assert_eq!(flags.ulong_size(), Some(mem::size_of::<uLong>()));
+        assert_eq!(flags.pointer_size(), Some(mem::size_of::<voidpf>()));
+    }
+
+    #[test]
+    fn linked_library_passes() {
+        assert_eq!(check_abi(), Ok(()));
+        assert_abi();
+    }
+
+    #[test]
+    fn parses_versions() {
+        assert_eq!(vernum("1.2.13"), Some(0x12d0));
+        assert_eq!(vernum("1.2.11.1-motley"), Some(0x12b1));
+        assert_eq!(vernum("1.3.1.zlib-ng"), Some(0x1310));
+        assert_eq!(vernum("1.3"), Some(0x1300));
+        assert_eq!(vernum("2.2.99"), Some(0x22f0));
+        assert_eq!(vernum("1"), None);
+        assert_eq!(vernum("zlib"), None);
+    }
+
+    #[cfg(not(zng))]
+    #[test]
+    fn version_mismatches() {
+        assert_eq!(version_mismatch("1.15.15"), None);
+        assert!(version_mismatch("2.0.0").is_some());
+        assert!(version_mismatch("0.9.9").is_some());
+        assert!(version_mismatch("garbage").is_some());
+    }
+
+    #[test]
+    fn report_display() {
+        let report = AbiReport {
+            version: "9.9".to_string(),
+            flags: CompileFlags::from_bits(0x65),
+            mismatches: vec![
+                AbiMismatch::Version {
+                    expected: "1.2.9 or later".to_string(),
+                    found: "9.9".to_string(),
+                },
+                AbiMismatch::TypeSize {
+                    name: "uLong",
+                    expected: 8,
+                    found: Some(4),
+                },
+                AbiMismatch::TypeSize {
+                    name: "uInt",
+                    expected: 4,
+                    found: None,
+                },
+                AbiMismatch::StreamSize { expected: 112 },
+            ],
+        };
+        assert_eq!(
+            report.to_string(),
+            "zlib 9.9 (flags 0x65) doesn't match the bindings: \
+             expected version 1.2.9 or later, found 9.9; \
+             `uLong` is 4 bytes, expected 8; \
+             `uInt` has an unusual size, expected 4; \
+             `z_stream` is not 112 bytes"
+        );
+    }
+
+    #[test]
+    fn identifies_the_linked_library() {
+        let info = implementation();
+        let version = unsafe { c_str(zlibVersion()) };
+        assert_eq!(info.version, version);
+        if cfg!(zng) {
+            assert_eq!(info.implementation, Implementation::ZlibNgNative);
+            assert_eq!(info.ng_version.as_ref(), Some(&version));
+        } else if version.contains("zlib-ng") || info.ng_version.is_some() {
+            assert_eq!(info.implementation, Implementation::ZlibNgCompat);
+        } else {
+            assert_eq!(info.implementation, Implementation::StockZlib);
+        }
+    }
+
+    #[cfg(not(zng))]
+    #[test]
+    fn classifies_by_source_and_version() {
+        use Implementation::*;
+
+        let cases = [
+            (Some("zlib-ng-compat"), "1.3.1.zlib-ng", false, ZlibNgCompat),
+            (Some("zlib-ng-compat"), "1.3.1", true, ZlibNgCompat),
+            (Some("zlib-ng-compat"), "1.3.1", false, Unknown),
+            (Some("bundled-zlib"), "1.3.1", false, StockZlib),
+            (Some("bundled-zlib"), "1.3.1.zlib-ng", false, Unknown),
+            (Some("bundled-zlib"), "1.3.1", true, Unknown),
+            (Some("bundled-zlib"), "2.0.0", false, Unknown),
+            (Some("pkg-config"), "1.2.13", false, StockZlib),
+            // A distribution's zlib-ng-compat `libz.so`.
+            (Some("pkg-config"), "1.3.1.zlib-ng", false, ZlibNgCompat),
+            (Some("pkg-config"), "1.3.1", true, ZlibNgCompat),
+            (Some("vcpkg"), "1.3.1", false, StockZlib),
+            (Some("smoke-test"), "1.2.13.zlib-ng", false, ZlibNgCompat),
+            (Some("platform"), "1.2.13", false, StockZlib),
+            (Some("pkg-config"), "9.9", false, Unknown),
+            // Without the build script, only the library is there to go on.
+            (None, "1.2.13", false, StockZlib),
+            (None, "1.3.1.zlib-ng", false, ZlibNgCompat),
+            (None, "1.3.1", true, ZlibNgCompat),
+            (None, "unknown", false, Unknown),
+            (Some("elsewhere"), "1.2.13", false, Unknown),
+        ];
+        for (source, version, exports_ng_version, expected) in cases {
+            assert_eq!(
+                classify(source, version, exports_ng_version),
+                expected,
+                "{:?} {} {}",
+                source,
+                version,
+                exports_ng_version
+            );
+        }
+    }
+}
@@ -0,0 +1,429 @@
+use std::io::{self, BufRead, Read, Write};
+
+use super::{Deflate, DeflateParams};
+use super::{Flush, Format, Inflate, InflateParams, Level, Status, ZError};
+
+// Size of the compressed-output buffer an encoder fills before handing it to
+// the inner writer.
+const BUF_SIZE: usize = 32 * 1024;
+
+/// Shared implementation of the encoders.
+struct Writer<W: Write> {
+    inner: Option<W>,
+    stream: Deflate,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> Writer<W> {
+    fn new(inner: W, level: Level, format: Format) -> Result<Writer<W>, ZError> {
+        let params = DeflateParams::new().level(level).format(format);
+        Ok(Writer {
+            inner: Some(inner),
+            stream: Deflate::with_params(&params)?,
+            buf: Vec::with_capacity(BUF_SIZE),
+        })
+    }
+
+    fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    fn get_mut(&mut self) -> &mut W {
+        self.inner.as_mut().unwrap()
+    }
+
+    /// Writes out everything in `buf`.
+    fn dump(&mut self) -> io::Result<()> {
+        while !self.buf.is_empty() {
+            let n = self.inner.as_mut().unwrap().write(&self.buf)?;
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            self.buf.drain(..n);
+        }
+        Ok(())
+    }
+
+    /// Compresses `input` into the free space of `buf`.
+    fn compress(&mut self, input: &[u8], flush: Flush) -> io::Result<usize> {
+        let len = self.buf.len();
+        self.buf.resize(BUF_SIZE, 0);
+        let res = self.stream.compress(input, &mut self.buf[len..], flush);
+        let (consumed, produced) = match res {
+            Ok(progress) => progress,
+            Err(e) => {
+                self.buf.truncate(len);
+                return Err(e.into());
+            }
+        };
+        self.buf.truncate(len + produced);
+        Ok(consumed)
+    }
+
+    /// Runs `flush` until zlib has nothing more to give.
+    fn drain(&mut self, flush: Flush) -> io::Result<()> {
+        loop {
+            self.dump()?;
+            self.compress(&[], flush)?;
+            // Short of `Z_FINISH`, a partially filled buffer means zlib ran out
+            // of output to produce before it ran out of room.
+            let done = if flush == Flush::Finish {
+                self.stream.is_finished()
+            } else {
+                self.buf.len() < BUF_SIZE
+            };
+            if done {
+                return self.dump();
+            }
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.stream.is_finished() {
+            return self.dump();
+        }
+        self.drain(Flush::Finish)
+    }
+}
+
+impl<W: Write> Write for Writer<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            self.dump()?;
+            let consumed = self.compress(data, Flush::None)?;
+            // zlib may need to empty its output before accepting more input.
+            if consumed > 0 {
+                return Ok(consumed);
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.stream.is_finished() {
+            self.drain(Flush::Sync)?;
+        }
+        self.get_mut().flush()
+    }
+}
+
+impl<W: Write> Drop for Writer<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.finish();
+        }
+    }
+}
+
+/// Shared implementation of the decoders.
+struct Reader<R: BufRead> {
+    inner: R,
+    stream: Inflate,
+    multi_member: bool,
+    done: bool,
+}
+
+impl<R: BufRead> Reader<R> {
+    fn new(inner: R, format: Format, multi_member: bool) -> Result<Reader<R>, ZError> {
+        Ok(Reader {
+            inner,
+            stream: Inflate::with_params(&InflateParams::new().format(format))?,
+            multi_member,
+            done: false,
+        })
+    }
+}
+
+impl<R: BufRead> Read for Reader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if out.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if self.done {
+                return Ok(0);
+            }
+            let input = self.inner.fill_buf()?;
+            let eof = input.is_empty();
+            let flush = if eof { Flush::Finish } else { Flush::None };
+            let (status, consumed, produced) = self.stream.decompress(input, out, flush)?;
+            self.inner.consume(consumed);
+
+            match status {
+                Status::StreamEnd => {
+                    // Like `gzip -d`, keep going if another member follows.
+                    if self.multi_member && !self.inner.fill_buf()?.is_empty() {
+                        self.stream.reset()?;
+                    } else {
+                        self.done = true;
+                    }
+                }
+                Status::NeedDict(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "stream requires a preset dictionary",
+                    ));
+                }
+                Status::Ok | Status::BufError => {
+                    if produced == 0 && eof {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "compressed stream ended early",
+                        ));
+                    }
+                }
+            }
+            if produced > 0 {
+                return Ok(produced);
+            }
+        }
+    }
+}
+
+macro_rules! encoder {
+    ($(#[$attr:meta])* $name:ident, $format:expr) => {
+        $(#[$attr])*
+        pub struct $name<W: Write> {
+            inner: Writer<W>,
+        }
+
+        impl<W: Write> $name<W> {
+            /// Creates an encoder that writes compressed data to `inner`.
+            pub fn new(inner: W, level: Level) -> Result<$name<W>, ZError> {
+                Ok($name {
+                    inner: Writer::new(inner, level, $format)?,
+                })
+            }
+
+            /// The underlying writer.
+            pub fn get_ref(&self) -> &W {
+                self.inner.get_ref()
+            }
+
+            /// The underlying writer.
+            ///
+            /// Writing to it directly will corrupt the compressed output.
+            pub fn get_mut(&mut self) -> &mut W {
+                self.inner.get_mut()
+            }
+
+            /// Finishes the stream with `Z_FINISH` and returns the underlying
+            /// writer.
+            ///
+            /// Dropping the encoder also finishes the stream, but any error
+            /// is lost.
+            pub fn finish(mut self) -> io::Result<W> {
+                self.inner.finish()?;
+                Ok(self.inner.inner.take().unwrap())
+            }
+        }
+
+        impl<W: Write> Write for $name<W> {
+            fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+                self.inner.write(data)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                self.inner.flush()
+            }
+        }
+    };
+}
+
+macro_rules! decoder {
+    ($(#[$attr:meta])* $name:ident, $format:expr, $multi_member:expr) => {
+        $(#[$attr])*
+        pub struct $name<R: BufRead> {
+            inner: Reader<R>,
+        }
+
+        impl<R: BufRead> $name<R> {
+            /// Creates a decoder that reads compressed data from `inner`.
+            pub fn new(inner: R) -> Result<$name<R>, ZError> {
+                Ok($name {
+                    inner: Reader::new(inner, $format, $multi_member)?,
+                })
+            }
+
+            /// The underlying reader.
+            pub fn get_ref(&self) -> &R {
+                &self.inner.inner
+            }
+
+            /// The underlying reader.
+            ///
+            /// Reading from it directly will corrupt the decompressed output.
+            pub fn get_mut(&mut self) -> &mut R {
+                &mut self.inner.inner
+            }
+
+            /// Returns the underlying reader. Any input after the end of the
+            /// compressed stream is still available from it.
+            pub fn into_inner(self) -> R {
+                self.inner.inner
+            }
+        }
+
+        impl<R: BufRead> Read for $name<R> {
+            fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+                self.inner.read(out)
+            }
+        }
+    };
+}
+
+encoder!(
+    /// Compresses raw deflate data into a writer.
+    DeflateEncoder,
+    Format::Raw
+);
+encoder!(
+    /// Compresses zlib-format data into a writer.
+    ZlibEncoder,
+    Format::Zlib
+);
+encoder!(
+    /// Compresses gzip-format data into a writer.
+    GzEncoder,
+    Format::Gzip
+);
+
+decoder!(
+    /// Decompresses raw deflate data from a buffered reader.
+    DeflateDecoder,
+    Format::Raw,
+    false
+);
+decoder!(
+    /// Decompresses zlib-format data from a buffered reader.
+    ZlibDecoder,
+    Format::Zlib,
+    false
+);
+decoder!(
+    /// Decompresses gzip-format data from a buffered reader.
+    ///
+    /// Concatenated gzip members are decoded one after the other into a
+    /// single stream, the same way `gzip -d` handles them.
+    GzDecoder,
+    Format::Gzip,
+    true
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i % 251) as u8 ^ (i / 1024) as u8)
+            .collect()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Level::DEFAULT).unwrap();
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn read_all(mut reader: impl Read) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn round_trip_each_format() {
+        let data = sample(200_000);
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Level::FAST).unwrap();
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let decoder = DeflateDecoder::new(&compressed[..]).unwrap();
+        assert_eq!(read_all(decoder).unwrap(), data);
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Level::BEST).unwrap();
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let decoder = ZlibDecoder::new(&compressed[..]).unwrap();
+        assert_eq!(read_all(decoder).unwrap(), data);
+
+        let compressed = gzip(&data);
+        assert_eq!(&compressed[..2], &[0x1f, 0x8b]);
+        // A tiny input buffer makes the decoder refill many times.
+        let decoder = GzDecoder::new(BufReader::with_capacity(3, &compressed[..])).unwrap();
+        assert_eq!(read_all(decoder).unwrap(), data);
+    }
+
+    #[test]
+    fn drop_finishes_the_stream() {
+        let mut compressed = Vec::new();
+        {
+            let mut encoder = ZlibEncoder::new(&mut compressed, Level::DEFAULT).unwrap();
+            encoder.write_all(b"dropped").unwrap();
+        }
+        let decoder = ZlibDecoder::new(&compressed[..]).unwrap();
+        assert_eq!(read_all(decoder).unwrap(), b"dropped");
+    }
+
+    #[test]
+    fn flush_makes_output_decodable() {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Level::DEFAULT).unwrap();
+        encoder.write_all(b"first part").unwrap();
+        encoder.flush().unwrap();
+        let prefix = encoder.get_ref().clone();
+
+        let mut decoder = DeflateDecoder::new(&prefix[..]).unwrap();
+        let mut out = [0; 10];
+        decoder.read_exact(&mut out).unwrap();
+        assert_eq!(&out, b"first part");
+    }
+
+    #[test]
+    fn gzip_multi_member() {
+        let mut compressed = gzip(b"hello, ");
+        compressed.extend(gzip(b""));
+        compressed.extend(gzip(b"world"));
+        let decoder = GzDecoder::new(&compressed[..]).unwrap();
+        assert_eq!(read_all(decoder).unwrap(), b"hello, world");
+    }
+
+    #[test]
+    fn zlib_leaves_trailing_input() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Level::DEFAULT).unwrap();
+        encoder.write_all(b"payload").unwrap();
+        let mut compressed = encoder.finish().unwrap();
+        compressed.extend_from_slice(b"trailer");
+
+        let mut decoder = ZlibDecoder::new(&compressed[..]).unwrap();
+        assert_eq!(read_all(&mut decoder).unwrap(), b"payload");
+        assert_eq!(decoder.into_inner(), b"trailer");
+    }
+
+    #[test]
+    fn truncated_input() {
+        let data = sample(50_000);
+        let compressed = gzip(&data);
+        for &len in &[0, 5, compressed.len() / 2, compressed.len() - 1] {
+            let decoder = GzDecoder::new(&compressed[..len]).unwrap();
+            let err = read_all(decoder).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "at {}", len);
+        }
+    }
+
+    #[test]
+    fn corrupt_input() {
+        let mut compressed = gzip(&sample(1_000));
+        // Break the CRC-32 in the trailer.
+        let crc = compressed.len() - 8;
+        compressed[crc] ^= 0xff;
+        let err = read_all(GzDecoder::new(&compressed[..]).unwrap()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let err = read_all(ZlibDecoder::new(&b"garbage"[..]).unwrap()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,167 @@
+use std::convert::TryFrom;
+use std::os::raw::c_int;
+
+use super::{Level, ZError};
+use crate::*;
+
+/// Compresses `input` in zlib format with `compress2`, appending the result
+/// to `out`.
+///
+/// `out` is grown by `compressBound(input.len())` up front, and the output is
+/// written straight into its spare capacity. Returns the number of bytes
+/// appended.
+pub fn compress_to_vec(input: &[u8], level: Level, out: &mut Vec<u8>) -> Result<usize, ZError> {
+    let source_len = to_z_size(input.len())?;
+    let bound = unsafe { compressBound(source_len) };
+    // `compressBound` returns `uLong` or `size_t` depending on the backend.
+    #[allow(clippy::useless_conversion)]
+    let bound = usize::try_from(bound).map_err(|_| too_large())?;
+    out.reserve(bound);
+
+    let mut dest_len = to_z_size(out.capacity() - out.len())?;
+    let ret = unsafe {
+        compress2(
+            out.as_mut_ptr().add(out.len()),
+            &mut dest_len,
+            input.as_ptr(),
+            source_len,
+            level.as_raw(),
+        )
+    };
+    unsafe { append(out, ret, dest_len) }
+}
+
+/// Decompresses zlib-format `input` with `uncompress`, appending the result
+/// to `out`.
+///
+/// The first attempt reserves `size_hint` bytes, or a guess based on the input
+/// size. Whenever zlib reports that the output didn't fit, the reservation is
+/// doubled, up to `max_size` bytes of output; beyond that a `Z_BUF_ERROR` is
+/// returned. Returns the number of bytes appended.
+pub fn uncompress_to_vec(
+    input: &[u8],
+    size_hint: Option<usize>,
+    max_size: usize,
+    out: &mut Vec<u8>,
+) -> Result<usize, ZError> {
+    let source_len = to_z_size(input.len())?;
+    let mut size = size_hint
+        .unwrap_or_else(|| input.len().saturating_mul(4))
+        .clamp(1, max_size.max(1));
+    loop {
+        out.reserve(size);
+        let spare = (out.capacity() - out.len()).min(max_size);
+        let mut dest_len = to_z_size(spare)?;
+        let ret = unsafe {
+            uncompress(
+                out.as_mut_ptr().add(out.len()),
+                &mut dest_len,
+                input.as_ptr(),
+                source_len,
+            )
+        };
+        if ret != Z_BUF_ERROR {
+            return unsafe { append(out, ret, dest_len) };
+        }
+        if spare >= max_size {
+            return Err(ZError::with_message(
+                Z_BUF_ERROR,
+                "decompressed data is larger than the maximum size",
+            ));
+        }
+        size = spare.saturating_mul(2).min(max_size);
+    }
+}
+
+/// Extends `out` over the `len` bytes zlib wrote past its end, if `ret`
+/// reports success.
+unsafe fn append(out: &mut Vec<u8>, ret: c_int, len: z_size) -> Result<usize, ZError> {
+    if ret != Z_OK {
+        return Err(ZError::new(ret));
+    }
+    #[allow(clippy::unnecessary_cast)]
+    let len = len as usize;
+    out.set_len(out.len() + len);
+    Ok(len)
+}
+
+fn to_z_size(len: usize) -> Result<z_size, ZError> {
+    z_size::try_from(len).map_err(|_| too_large())
+}
+
+fn too_large() -> ZError {
+    ZError::with_message(Z_BUF_ERROR, "buffer is too large for zlib")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::safe::ErrorCode;
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i % 251) as u8 ^ (i / 1024) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn round_trip_appends() {
+        let data = sample(100_000);
+        let mut compressed = b"prefix".to_vec();
+        let n = compress_to_vec(&data, Level::DEFAULT, &mut compressed).unwrap();
+        assert_eq!(compressed.len(), 6 + n);
+        assert_eq!(&compressed[..6], b"prefix");
+
+        let mut out = b"kept".to_vec();
+        let n = uncompress_to_vec(&compressed[6..], None, usize::MAX, &mut out).unwrap();
+        assert_eq!(n, data.len());
+        assert_eq!(&out[..4], b"kept");
+        assert_eq!(&out[4..], &data[..]);
+    }
+
+    #[test]
+    fn grows_from_a_small_hint() {
+        let data = sample(300_000);
+        let mut compressed = Vec::new();
+        compress_to_vec(&data, Level::BEST, &mut compressed).unwrap();
+        let mut out = Vec::new();
+        uncompress_to_vec(&compressed, Some(1), usize::MAX, &mut out).unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn max_size() {
+        let data = sample(10_000);
+        let mut compressed = Vec::new();
+        compress_to_vec(&data, Level::DEFAULT, &mut compressed).unwrap();
+
+        let mut out = Vec::new();
+        uncompress_to_vec(&compressed, Some(16), data.len(), &mut out).unwrap();
+        assert_eq!(out, data);
+
+        let mut out = Vec::new();
+        let err = uncompress_to_vec(&compressed, Some(16), data.len() - 1, &mut out).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Buf);
+        assert!(err.message().unwrap().contains("maximum size"));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn empty_input() {
+        let mut compressed = Vec::new();
+        compress_to_vec(&[], Level::DEFAULT, &mut compressed).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(
+            uncompress_to_vec(&compressed, None, 0, &mut out).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn corrupt_input() {
+        let mut out = Vec::new();
+        let err = uncompress_to_vec(b"not zlib", None, 1024, &mut out).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Data);
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,232 @@
+use std::fs::File;
+use std::io;
+use std::thread;
+
+use super::{Adler32, Crc32};
+
+// Below this, the cost of spawning a thread outweighs the work it saves.
+const MIN_CHUNK: usize = 1024 * 1024;
+
+// Size of the buffer each thread reads a file through.
+const READ_BUF: usize = 256 * 1024;
+
+/// The operations the parallel drivers need from a checksum.
+trait Checksum: Default + Send {
+    fn update(&mut self, data: &[u8]);
+    fn combine(&mut self, other: &Self);
+}
+
+impl Checksum for Crc32 {
+    fn update(&mut self, data: &[u8]) {
+        Crc32::update(self, data)
+    }
+
+    fn combine(&mut self, other: &Crc32) {
+        Crc32::combine(self, other.finalize(), other.amount())
+    }
+}
+
+impl Checksum for Adler32 {
+    fn update(&mut self, data: &[u8]) {
+        Adler32::update(self, data)
+    }
+
+    fn combine(&mut self, other: &Adler32) {
+        Adler32::combine(self, other.finalize(), other.amount())
+    }
+}
+
+/// Computes the CRC-32 of `data`, splitting it across threads.
+///
+/// Each thread checksums one contiguous chunk and the results are merged
+/// with `crc32_combine`, so the result is identical to feeding all of `data`
+/// to a single [`Crc32`].
+pub fn crc32_parallel(data: &[u8]) -> Crc32 {
+    checksum_slice(data)
+}
+
+/// Computes the Adler-32 of `data`, splitting it across threads.
+///
+/// The results are merged with `adler32_combine`; see [`crc32_parallel`].
+pub fn adler32_parallel(data: &[u8]) -> Adler32 {
+    checksum_slice(data)
+}
+
+/// Computes the CRC-32 of the whole of `file`, reading chunks of it on
+/// separate threads.
+///
+/// Reads are positional, so the file's cursor is left alone on Unix. On
+/// Windows it ends up somewhere unspecified.
+#[cfg(any(unix, windows))]
+pub fn crc32_file_parallel(file: &File) -> io::Result<Crc32> {
+    checksum_file(file)
+}
+
+/// Computes the Adler-32 of the whole of `file`, reading chunks of it on
+/// separate threads. See [`crc32_file_parallel`].
+#[cfg(any(unix, windows))]
+pub fn adler32_file_parallel(file: &File) -> io::Result<Adler32> {
+    checksum_file(file)
+}
+
+/// How many pieces to split `len` bytes into, and how big each one is.
+fn split(len: u64) -> (usize, u64) {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get()) as u64;
+    let chunks = (len / MIN_CHUNK as u64).clamp(1, threads);
+    (chunks as usize, len.div_ceil(chunks).max(1))
+}
+
+fn merge<C: Checksum>(parts: impl IntoIterator<Item = C>) -> C {
+    let mut sum = C::default();
+    for part in parts {
+        sum.combine(&part);
+    }
+    sum
+}
+
+fn checksum_slice<C: Checksum>(data: &[u8]) -> C {
+    let (chunks, chunk_size) = split(data.len() as u64);
+    if chunks == 1 {
+        let mut sum = C::default();
+        sum.update(data);
+        return sum;
+    }
+    thread::scope(|s| {
+        let handles = data
+            .chunks(chunk_size as usize)
+            .map(|chunk| {
+                s.spawn(move || {
+                    let mut sum = C::default();
+                    sum.update(chunk);
+                    sum
+                })
+            })
+            .collect::<Vec<_>>();
+        merge(handles.into_iter().map(|h| h.join().unwrap()))
+    })
+}
+
+#[cfg(any(unix, windows))]
+fn checksum_file<C: Checksum>(file: &File) -> io::Result<C> {
+    let len = file.metadata()?.len();
+    let (chunks, chunk_size) = split(len);
+    thread::scope(|s| {
+        let handles = (0..chunks as u64)
+            .map(|i| {
+                let start = i * chunk_size;
+                let end = (start + chunk_size).min(len);
+                s.spawn(move || checksum_range::<C>(file, start, end))
+            })
+            .collect::<Vec<_>>();
+        let parts = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(merge(parts))
+    })
+}
+
+#[cfg(any(unix, windows))]
+fn checksum_range<C: Checksum>(file: &File, mut pos: u64, end: u64) -> io::Result<C> {
+    let mut sum = C::default();
+    let mut buf = vec![0; READ_BUF.min((end - pos) as usize)];
+    while pos < end {
+        let want = buf.len().min((end - pos) as usize);
+        let n = read_at(file, &mut buf[..want], pos)?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "file shrank while it was being checksummed",
+            ));
+        }
+        sum.update(&buf[..n]);
+        pos += n as u64;
+    }
+    Ok(sum)
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+    loop {
+        match file.read_at(buf, offset) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            res => return res,
+        }
+    }
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::windows::fs::FileExt;
+    loop {
+        match file.seek_read(buf, offset) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            res => return res,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i % 251) as u8 ^ (i / 1024) as u8)
+            .collect()
+    }
+
+    fn serial<C: Checksum>(data: &[u8]) -> C {
+        let mut sum = C::default();
+        sum.update(data);
+        sum
+    }
+
+    #[test]
+    fn matches_serial() {
+        let data = sample(5 * MIN_CHUNK + 12_345);
+        assert_eq!(crc32_parallel(&data), serial::<Crc32>(&data));
+        assert_eq!(adler32_parallel(&data), serial::<Adler32>(&data));
+        assert_eq!(crc32_parallel(&[]), Crc32::new());
+        assert_eq!(adler32_parallel(&[]), Adler32::new());
+    }
+
+    // `split` depends on the number of CPUs, so the merging is also checked
+    // with a fixed, uneven split.
+    #[test]
+    fn merge_matches_serial() {
+        let data = sample(300_000);
+        let bounds = [0, 1, 70_000, 70_000, 200_003, 300_000];
+        let crc = merge(
+            bounds
+                .windows(2)
+                .map(|w| serial::<Crc32>(&data[w[0]..w[1]])),
+        );
+        assert_eq!(crc, serial::<Crc32>(&data));
+        let adler = merge(
+            bounds
+                .windows(2)
+                .map(|w| serial::<Adler32>(&data[w[0]..w[1]])),
+        );
+        assert_eq!(adler, serial::<Adler32>(&data));
+    }
+
+    #[cfg(any(unix, windows))]
+    #[test]
+    fn file_matches_serial() {
+        let path = std::env::temp_dir().join(format!("libz-sys-{}-parallel", std::process::id()));
+        let data = sample(3 * MIN_CHUNK + 7);
+        std::fs::write(&path, &data).unwrap();
+        let file = File::open(&path).unwrap();
+        let crc = crc32_file_parallel(&file).unwrap();
+        let adler = adler32_file_parallel(&file).unwrap();
+        std::fs::write(&path, b"").unwrap();
+        let empty = crc32_file_parallel(&File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(crc, serial::<Crc32>(&data));
+        assert_eq!(adler, serial::<Adler32>(&data));
+        assert_eq!(empty, Crc32::new());
+    }
+}
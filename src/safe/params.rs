@@ -0,0 +1,393 @@
+#[cfg(zng)]
+use std::fmt;
+use std::os::raw::c_int;
+
+use super::ZError;
+use crate::*;
+
+/// A compression level between 0 and 9, or zlib's default.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Level(c_int);
+
+impl Level {
+    /// `Z_DEFAULT_COMPRESSION`, currently equivalent to level 6.
+    pub const DEFAULT: Level = Level(Z_DEFAULT_COMPRESSION);
+    /// `Z_NO_COMPRESSION`: stored blocks only.
+    pub const NONE: Level = Level(Z_NO_COMPRESSION);
+    /// `Z_BEST_SPEED`
+    pub const FAST: Level = Level(Z_BEST_SPEED);
+    /// `Z_BEST_COMPRESSION`
+    pub const BEST: Level = Level(Z_BEST_COMPRESSION);
+
+    /// A level between 0 and 9.
+    pub fn new(level: u32) -> Result<Level, ZError> {
+        if level > 9 {
+            return Err(invalid("compression level must be between 0 and 9"));
+        }
+        Ok(Level(level as c_int))
+    }
+
+    /// The value passed as `level`.
+    pub fn as_raw(self) -> c_int {
+        self.0
+    }
+}
+
+impl Default for Level {
+    fn default() -> Level {
+        Level::DEFAULT
+    }
+}
+
+/// The `strategy` passed to `deflateInit2_` and `deflateParams`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Strategy {
+    /// `Z_DEFAULT_STRATEGY`
+    #[default]
+    Default,
+    /// `Z_FILTERED`
+    Filtered,
+    /// `Z_HUFFMAN_ONLY`
+    HuffmanOnly,
+    /// `Z_RLE`
+    Rle,
+    /// `Z_FIXED`
+    Fixed,
+}
+
+impl Strategy {
+    /// The value passed as `strategy`.
+    pub fn as_raw(self) -> c_int {
+        match self {
+            Strategy::Default => Z_DEFAULT_STRATEGY,
+            Strategy::Filtered => Z_FILTERED,
+            Strategy::HuffmanOnly => Z_HUFFMAN_ONLY,
+            Strategy::Rle => Z_RLE,
+            Strategy::Fixed => Z_FIXED,
+        }
+    }
+}
+
+/// A parameter of a running zlib-ng deflate stream, set with
+/// [`Deflate::set_params`](super::Deflate::set_params).
+///
+/// Only available with zlib-ng in native mode.
+#[cfg(zng)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DeflateParam {
+    /// `Z_DEFLATE_LEVEL`
+    Level(Level),
+    /// `Z_DEFLATE_STRATEGY`
+    Strategy(Strategy),
+    /// `Z_DEFLATE_REPRODUCIBLE`: whether the output has to be identical on
+    /// every run of the same zlib-ng build, ruling out optimizations that
+    /// would make it vary. Off by default.
+    Reproducible(bool),
+}
+
+#[cfg(zng)]
+impl DeflateParam {
+    /// The `Z_DEFLATE_*` identifier and the `int` value passed for it.
+    pub fn to_raw(self) -> (zng_deflate_param, c_int) {
+        match self {
+            DeflateParam::Level(level) => (Z_DEFLATE_LEVEL, level.as_raw()),
+            DeflateParam::Strategy(strategy) => (Z_DEFLATE_STRATEGY, strategy.as_raw()),
+            DeflateParam::Reproducible(on) => (Z_DEFLATE_REPRODUCIBLE, c_int::from(on)),
+        }
+    }
+}
+
+/// Describes the parameter the way an error message would, such as
+/// `level 9` or `strategy filtered`.
+#[cfg(zng)]
+impl fmt::Display for DeflateParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            DeflateParam::Level(Level::DEFAULT) => f.write_str("default level"),
+            DeflateParam::Level(level) => write!(f, "level {}", level.as_raw()),
+            DeflateParam::Strategy(strategy) => {
+                let name = match strategy {
+                    Strategy::Default => "default",
+                    Strategy::Filtered => "filtered",
+                    Strategy::HuffmanOnly => "Huffman only",
+                    Strategy::Rle => "RLE",
+                    Strategy::Fixed => "fixed",
+                };
+                write!(f, "strategy {}", name)
+            }
+            DeflateParam::Reproducible(on) => {
+                write!(f, "reproducible {}", if on { "on" } else { "off" })
+            }
+        }
+    }
+}
+
+/// The container around the deflate data.
+///
+/// zlib selects this by adjusting `windowBits`; [`Format::window_bits`] does
+/// that adjustment.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// A zlib header and Adler-32 trailer.
+    #[default]
+    Zlib,
+    /// A gzip header and CRC-32 trailer (`windowBits + 16`).
+    Gzip,
+    /// Bare deflate data with no header or trailer (`-windowBits`).
+    Raw,
+    /// Either zlib or gzip, detected from the header (`windowBits + 32`).
+    /// Only valid for decompression.
+    Auto,
+}
+
+impl Format {
+    /// The `windowBits` value that selects this format with a window of
+    /// `bits`.
+    pub fn window_bits(self, bits: WindowBits) -> c_int {
+        let bits = bits.as_raw();
+        match self {
+            Format::Zlib => bits,
+            Format::Gzip => bits + 16,
+            Format::Raw => -bits,
+            Format::Auto => bits + 32,
+        }
+    }
+}
+
+/// The base-two logarithm of the window size, between 8 and 15.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WindowBits(u8);
+
+impl WindowBits {
+    /// The largest window, 32 KiB. This is zlib's default.
+    pub const MAX: WindowBits = WindowBits(15);
+    /// The smallest window, 256 bytes.
+    pub const MIN: WindowBits = WindowBits(8);
+
+    /// A window of `2^bits` bytes.
+    pub fn new(bits: u8) -> Result<WindowBits, ZError> {
+        if !(8..=15).contains(&bits) {
+            return Err(invalid("window bits must be between 8 and 15"));
+        }
+        Ok(WindowBits(bits))
+    }
+
+    /// The window size without any format adjustment.
+    pub fn as_raw(self) -> c_int {
+        self.0 as c_int
+    }
+}
+
+impl Default for WindowBits {
+    fn default() -> WindowBits {
+        WindowBits::MAX
+    }
+}
+
+/// How much memory deflate uses for its internal state, between 1 and 9.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MemLevel(u8);
+
+impl MemLevel {
+    /// zlib's default, 8.
+    pub const DEFAULT: MemLevel = MemLevel(8);
+    /// The largest memory level, 9.
+    pub const MAX: MemLevel = MemLevel(9);
+
+    /// A memory level between 1 and 9.
+    pub fn new(level: u8) -> Result<MemLevel, ZError> {
+        if !(1..=9).contains(&level) {
+            return Err(invalid("memory level must be between 1 and 9"));
+        }
+        Ok(MemLevel(level))
+    }
+
+    /// The value passed as `memLevel`.
+    pub fn as_raw(self) -> c_int {
+        self.0 as c_int
+    }
+}
+
+impl Default for MemLevel {
+    fn default() -> MemLevel {
+        MemLevel::DEFAULT
+    }
+}
+
+/// Everything `deflateInit2_` needs, checked before the call.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DeflateParams {
+    level: Level,
+    format: Format,
+    window_bits: WindowBits,
+    mem_level: MemLevel,
+    strategy: Strategy,
+}
+
+impl DeflateParams {
+    /// zlib's defaults: default level and strategy, zlib format, a 32 KiB
+    /// window and memory level 8.
+    pub fn new() -> DeflateParams {
+        DeflateParams::default()
+    }
+
+    /// Sets the compression level.
+    pub fn level(mut self, level: Level) -> DeflateParams {
+        self.level = level;
+        self
+    }
+
+    /// Sets the container format. [`Format::Auto`] is rejected when the
+    /// stream is created.
+    pub fn format(mut self, format: Format) -> DeflateParams {
+        self.format = format;
+        self
+    }
+
+    /// Sets the window size.
+    pub fn window_bits(mut self, window_bits: WindowBits) -> DeflateParams {
+        self.window_bits = window_bits;
+        self
+    }
+
+    /// Sets the memory level.
+    pub fn mem_level(mut self, mem_level: MemLevel) -> DeflateParams {
+        self.mem_level = mem_level;
+        self
+    }
+
+    /// Sets the strategy.
+    pub fn strategy(mut self, strategy: Strategy) -> DeflateParams {
+        self.strategy = strategy;
+        self
+    }
+
+    /// The `level`, `windowBits`, `memLevel` and `strategy` arguments for
+    /// `deflateInit2_`, in that order.
+    pub fn to_raw(&self) -> Result<(c_int, c_int, c_int, c_int), ZError> {
+        if self.format == Format::Auto {
+            return Err(invalid(
+                "automatic format detection is only valid for inflate",
+            ));
+        }
+        // zlib silently bumps a zlib-format window of 8 up to 9, but refuses it
+        // for the other formats.
+        if self.window_bits == WindowBits::MIN && self.format != Format::Zlib {
+            return Err(invalid(
+                "a window bits value of 8 is only valid for the zlib format when compressing",
+            ));
+        }
+        Ok((
+            self.level.as_raw(),
+            self.format.window_bits(self.window_bits),
+            self.mem_level.as_raw(),
+            self.strategy.as_raw(),
+        ))
+    }
+}
+
+/// Everything `inflateInit2_` needs, checked before the call.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct InflateParams {
+    format: Format,
+    window_bits: WindowBits,
+}
+
+impl InflateParams {
+    /// zlib's defaults: zlib format with a 32 KiB window.
+    pub fn new() -> InflateParams {
+        InflateParams::default()
+    }
+
+    /// Sets the container format.
+    pub fn format(mut self, format: Format) -> InflateParams {
+        self.format = format;
+        self
+    }
+
+    /// Sets the window size. This must be at least the size the data was
+    /// compressed with.
+    pub fn window_bits(mut self, window_bits: WindowBits) -> InflateParams {
+        self.window_bits = window_bits;
+        self
+    }
+
+    /// The `windowBits` argument for `inflateInit2_` and `inflateReset2`.
+    pub fn to_raw(&self) -> c_int {
+        self.format.window_bits(self.window_bits)
+    }
+}
+
+fn invalid(message: &str) -> ZError {
+    ZError::with_message(Z_STREAM_ERROR, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::safe::{Deflate, ErrorCode};
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        assert!(Level::new(9).is_ok());
+        assert_eq!(Level::new(10).unwrap_err().code(), ErrorCode::Stream);
+        assert!(WindowBits::new(7).is_err());
+        assert!(WindowBits::new(16).is_err());
+        assert_eq!(WindowBits::new(8).unwrap(), WindowBits::MIN);
+        assert!(MemLevel::new(0).is_err());
+        assert!(MemLevel::new(10).is_err());
+        assert_eq!(MemLevel::new(9).unwrap(), MemLevel::MAX);
+    }
+
+    #[test]
+    fn format_adjusts_window_bits() {
+        let bits = WindowBits::new(12).unwrap();
+        assert_eq!(Format::Zlib.window_bits(bits), 12);
+        assert_eq!(Format::Gzip.window_bits(bits), 28);
+        assert_eq!(Format::Raw.window_bits(bits), -12);
+        assert_eq!(Format::Auto.window_bits(bits), 44);
+    }
+
+    #[test]
+    fn deflate_rejects_auto_format() {
+        let params = DeflateParams::new().format(Format::Auto);
+        let err = params.to_raw().unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Stream);
+        assert!(Deflate::with_params(&params).is_err());
+    }
+
+    #[test]
+    fn deflate_window_of_8_is_zlib_only() {
+        let params = DeflateParams::new().window_bits(WindowBits::MIN);
+        for &format in &[Format::Gzip, Format::Raw] {
+            let err = Deflate::with_params(&params.format(format)).err().unwrap();
+            assert!(err.message().unwrap().contains("window bits"));
+        }
+        assert!(Deflate::with_params(&params.format(Format::Zlib)).is_ok());
+    }
+
+    #[test]
+    fn defaults_match_zlib() {
+        let raw = DeflateParams::new().to_raw().unwrap();
+        assert_eq!(raw, (Z_DEFAULT_COMPRESSION, 15, 8, Z_DEFAULT_STRATEGY));
+        assert_eq!(InflateParams::new().to_raw(), 15);
+    }
+
+    #[cfg(zng)]
+    #[test]
+    fn deflate_param_display() {
+        assert_eq!(DeflateParam::Level(Level::BEST).to_string(), "level 9");
+        assert_eq!(
+            DeflateParam::Level(Level::DEFAULT).to_string(),
+            "default level"
+        );
+        assert_eq!(
+            DeflateParam::Strategy(Strategy::HuffmanOnly).to_string(),
+            "strategy Huffman only"
+        );
+        assert_eq!(
+            DeflateParam::Reproducible(true).to_string(),
+            "reproducible on"
+        );
+    }
+}
@@ -0,0 +1,747 @@
+use std::convert::TryFrom;
+use std::mem;
+use std::os::raw::{c_int, c_uint};
+use std::ptr;
+
+use super::header::HeaderSlot;
+#[cfg(zng)]
+use super::DeflateParam;
+use super::{new_stream, set_allocator, Allocator};
+use super::{DeflateParams, Format, GzHeader, HeaderError, InflateParams, Level, ZError};
+use crate::*;
+
+/// How much of the pending output a call should flush.
+///
+/// These map one-to-one onto the `Z_*_FLUSH` constants.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Flush {
+    /// `Z_NO_FLUSH`
+    None,
+    /// `Z_PARTIAL_FLUSH`
+    Partial,
+    /// `Z_SYNC_FLUSH`
+    Sync,
+    /// `Z_FULL_FLUSH`
+    Full,
+    /// `Z_FINISH`
+    Finish,
+    /// `Z_BLOCK`
+    Block,
+    /// `Z_TREES`
+    Trees,
+}
+
+impl Flush {
+    /// The raw value passed to `deflate` or `inflate`.
+    pub fn as_raw(self) -> c_int {
+        match self {
+            Flush::None => Z_NO_FLUSH,
+            Flush::Partial => Z_PARTIAL_FLUSH,
+            Flush::Sync => Z_SYNC_FLUSH,
+            Flush::Full => Z_FULL_FLUSH,
+            Flush::Finish => Z_FINISH,
+            Flush::Block => Z_BLOCK,
+            Flush::Trees => Z_TREES,
+        }
+    }
+}
+
+/// An owned deflate stream.
+///
+/// The `z_stream` lives on the heap because zlib keeps a pointer back to it in
+/// its internal state, and `deflateEnd` is called when this is dropped.
+pub struct Deflate {
+    stream: Box<z_stream>,
+    // Kept alive until after `deflateEnd`, which runs before fields drop.
+    _allocator: Option<Box<dyn Allocator + Send>>,
+    header: Option<Box<HeaderSlot>>,
+    finished: bool,
+}
+
+// zlib streams hold no thread-local state, and every call goes through
+// `&mut self`.
+unsafe impl Send for Deflate {}
+unsafe impl Sync for Deflate {}
+
+impl Deflate {
+    /// Creates a stream with the given level and format, and zlib's defaults
+    /// for everything else.
+    pub fn new(level: Level, format: Format) -> Result<Deflate, ZError> {
+        Deflate::with_params(&DeflateParams::new().level(level).format(format))
+    }
+
+    /// Creates a stream with `deflateInit2_`.
+    ///
+    /// The parameters are validated first, so invalid combinations are
+    /// reported without calling into zlib.
+    pub fn with_params(params: &DeflateParams) -> Result<Deflate, ZError> {
+        Deflate::init(params, new_stream(), None)
+    }
+
+    /// Like [`Deflate::with_params`], but with all of zlib's memory coming
+    /// from `allocator`.
+    pub fn with_allocator<A: Allocator + Send + 'static>(
+        params: &DeflateParams,
+        allocator: A,
+    ) -> Result<Deflate, ZError> {
+        let allocator = Box::new(allocator);
+        let mut stream = new_stream();
+        unsafe { set_allocator(&mut stream, &*allocator) };
+        Deflate::init(params, stream, Some(allocator))
+    }
+
+    fn init(
+        params: &DeflateParams,
+        mut stream: Box<z_stream>,
+        allocator: Option<Box<dyn Allocator + Send>>,
+    ) -> Result<Deflate, ZError> {
+        let (level, window_bits, mem_level, strategy) = params.to_raw()?;
+        let ret = unsafe {
+            deflateInit2_(
+                &mut *stream,
+                level,
+                Z_DEFLATED,
+                window_bits,
+                mem_level,
+                strategy,
+                zlibVersion(),
+                mem::size_of::<z_stream>() as c_int,
+            )
+        };
+        if ret != Z_OK {
+            return Err(unsafe { ZError::from_stream(ret, &stream) });
+        }
+        Ok(Deflate {
+            stream,
+            _allocator: allocator,
+            header: None,
+            finished: false,
+        })
+    }
+
+    /// Compresses as much of `input` into `output` as fits.
+    ///
+    /// Returns the number of bytes consumed from `input` and the number of
+    /// bytes written to `output`. Running out of input or output space is not
+    /// an error; call again with more of either. Once a call with
+    /// [`Flush::Finish`] has written everything, [`Deflate::is_finished`]
+    /// returns `true`.
+    pub fn compress(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        flush: Flush,
+    ) -> Result<(usize, usize), ZError> {
+        let (ret, consumed, produced) = unsafe {
+            run(&mut self.stream, input, output, |s| {
+                deflate(s, flush.as_raw())
+            })
+        };
+        match ret {
+            Z_OK | Z_BUF_ERROR => Ok((consumed, produced)),
+            Z_STREAM_END => {
+                self.finished = true;
+                Ok((consumed, produced))
+            }
+            ret => Err(self.error(ret)),
+        }
+    }
+
+    /// Whether `Z_FINISH` has completed and all output has been produced.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Sets the gzip header to write with `deflateSetHeader`.
+    ///
+    /// This only works for [`Format::Gzip`] streams, and must happen before
+    /// the first call to [`Deflate::compress`]. The header stays in effect
+    /// across [`Deflate::reset`].
+    pub fn set_header(&mut self, header: &GzHeader) -> Result<(), ZError> {
+        let mut slot = HeaderSlot::for_deflate(header)?;
+        match unsafe { deflateSetHeader(&mut *self.stream, slot.as_raw()) } {
+            Z_OK => {
+                self.header = Some(slot);
+                Ok(())
+            }
+            ret => Err(self.error(ret)),
+        }
+    }
+
+    /// Applies zlib-ng specific parameters with `zng_deflateSetParams`.
+    ///
+    /// Nothing is changed if any of `params` is rejected, for example because
+    /// the same parameter is listed twice. Changing the level or strategy of a
+    /// stream that already has input is subject to the same rules as
+    /// `deflateParams`.
+    #[cfg(zng)]
+    pub fn set_params(&mut self, params: &[DeflateParam]) -> Result<(), ZError> {
+        let mut values = params.iter().map(|p| p.to_raw().1).collect::<Vec<c_int>>();
+        let mut raw = params
+            .iter()
+            .zip(values.iter_mut())
+            .map(|(param, value)| zng_deflate_param_value {
+                param: param.to_raw().0,
+                buf: (value as *mut c_int).cast(),
+                size: mem::size_of::<c_int>(),
+                status: Z_OK,
+            })
+            .collect::<Vec<_>>();
+        match unsafe { zng_deflateSetParams(&mut *self.stream, raw.as_mut_ptr(), raw.len()) } {
+            Z_OK => Ok(()),
+            ret => match raw.iter().position(|value| value.status != Z_OK) {
+                Some(i) => Err(ZError::with_message(
+                    ret,
+                    format!("{} was rejected", params[i]),
+                )),
+                None => Err(self.error(ret)),
+            },
+        }
+    }
+
+    /// Resets the stream with `deflateReset`, keeping its parameters.
+    pub fn reset(&mut self) -> Result<(), ZError> {
+        match unsafe { deflateReset(&mut *self.stream) } {
+            Z_OK => {
+                self.finished = false;
+                Ok(())
+            }
+            ret => Err(self.error(ret)),
+        }
+    }
+
+    /// Total number of bytes consumed so far.
+    // `total_in` is `uLong` or `size_t` depending on the backend.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn total_in(&self) -> u64 {
+        self.stream.total_in as u64
+    }
+
+    /// Total number of bytes produced so far.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn total_out(&self) -> u64 {
+        self.stream.total_out as u64
+    }
+
+    /// The underlying stream, for calling functions this type doesn't wrap.
+    pub fn as_raw(&mut self) -> z_streamp {
+        &mut *self.stream
+    }
+
+    fn error(&self, code: c_int) -> ZError {
+        unsafe { ZError::from_stream(code, &self.stream) }
+    }
+}
+
+impl Drop for Deflate {
+    fn drop(&mut self) {
+        unsafe {
+            deflateEnd(&mut *self.stream);
+        }
+    }
+}
+
+/// The outcome of a successful [`Inflate::decompress`] call.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Status {
+    /// Progress was made; call again with more input or output space.
+    Ok,
+    /// The end of the compressed stream was reached.
+    StreamEnd,
+    /// A preset dictionary is needed. The value is the Adler-32 checksum of
+    /// the dictionary the compressor used; supply it with
+    /// [`Inflate::set_dictionary`] and call again.
+    NeedDict(u32),
+    /// No progress was possible. If all of the input was consumed, more input
+    /// is needed; otherwise the output buffer is full.
+    BufError,
+}
+
+/// An owned inflate stream.
+///
+/// Like [`Deflate`], the `z_stream` is kept on the heap and `inflateEnd` is
+/// called when this is dropped.
+pub struct Inflate {
+    stream: Box<z_stream>,
+    _allocator: Option<Box<dyn Allocator + Send>>,
+    header: Option<Box<HeaderSlot>>,
+}
+
+unsafe impl Send for Inflate {}
+unsafe impl Sync for Inflate {}
+
+impl Inflate {
+    /// Creates a stream for `format` with a 32 KiB window.
+    pub fn new(format: Format) -> Result<Inflate, ZError> {
+        Inflate::with_params(&InflateParams::new().format(format))
+    }
+
+    /// Creates a stream with `inflateInit2_`.
+    pub fn with_params(params: &InflateParams) -> Result<Inflate, ZError> {
+        Inflate::init(params, new_stream(), None)
+    }
+
+    /// Like [`Inflate::with_params`], but with all of zlib's memory coming
+    /// from `allocator`.
+    pub fn with_allocator<A: Allocator + Send + 'static>(
+        params: &InflateParams,
+        allocator: A,
+    ) -> Result<Inflate, ZError> {
+        let allocator = Box::new(allocator);
+        let mut stream = new_stream();
+        unsafe { set_allocator(&mut stream, &*allocator) };
+        Inflate::init(params, stream, Some(allocator))
+    }
+
+    fn init(
+        params: &InflateParams,
+        mut stream: Box<z_stream>,
+        allocator: Option<Box<dyn Allocator + Send>>,
+    ) -> Result<Inflate, ZError> {
+        let ret = unsafe {
+            inflateInit2_(
+                &mut *stream,
+                params.to_raw(),
+                zlibVersion(),
+                mem::size_of::<z_stream>() as c_int,
+            )
+        };
+        if ret != Z_OK {
+            return Err(unsafe { ZError::from_stream(ret, &stream) });
+        }
+        Ok(Inflate {
+            stream,
+            _allocator: allocator,
+            header: None,
+        })
+    }
+
+    /// Decompresses as much of `input` into `output` as fits.
+    ///
+    /// Returns the status along with the number of bytes consumed from
+    /// `input` and written to `output`. `Z_BUF_ERROR` is reported as
+    /// [`Status::BufError`] rather than as an error because it is recoverable.
+    // `adler` is `uLong` or `uint32_t` depending on the backend.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn decompress(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        flush: Flush,
+    ) -> Result<(Status, usize, usize), ZError> {
+        let (ret, consumed, produced) = unsafe {
+            run(&mut self.stream, input, output, |s| {
+                inflate(s, flush.as_raw())
+            })
+        };
+        let status = match ret {
+            Z_OK => Status::Ok,
+            Z_STREAM_END => Status::StreamEnd,
+            Z_NEED_DICT => Status::NeedDict(self.stream.adler as u32),
+            Z_BUF_ERROR => Status::BufError,
+            ret => return Err(self.error(ret)),
+        };
+        Ok((status, consumed, produced))
+    }
+
+    /// Supplies the preset dictionary requested by [`Status::NeedDict`].
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<(), ZError> {
+        let len = match uInt::try_from(dictionary.len()) {
+            Ok(len) => len,
+            Err(_) => return Err(ZError::new(Z_STREAM_ERROR)),
+        };
+        match unsafe { inflateSetDictionary(&mut *self.stream, dictionary.as_ptr(), len) } {
+            Z_OK => Ok(()),
+            ret => Err(self.error(ret)),
+        }
+    }
+
+    /// Asks zlib to record the gzip header with `inflateGetHeader`, keeping
+    /// up to the given number of bytes of the name, comment and extra field.
+    ///
+    /// The limits for the name and comment don't count their terminating
+    /// NUL: a name of exactly `name_max` bytes is returned whole, and only a
+    /// longer one is reported as truncated.
+    ///
+    /// This must happen after creating or resetting the stream and before
+    /// the first call to [`Inflate::decompress`]. The result is available
+    /// from [`Inflate::header`].
+    pub fn capture_header(
+        &mut self,
+        name_max: usize,
+        comment_max: usize,
+        extra_max: usize,
+    ) -> Result<(), ZError> {
+        let mut slot = HeaderSlot::for_inflate(name_max, comment_max, extra_max)?;
+        match unsafe { inflateGetHeader(&mut *self.stream, slot.as_raw()) } {
+            Z_OK => {
+                self.header = Some(slot);
+                Ok(())
+            }
+            ret => Err(self.error(ret)),
+        }
+    }
+
+    /// The gzip header requested with [`Inflate::capture_header`], once it
+    /// has been read in full.
+    ///
+    /// Returns `None` before then, if the stream isn't gzip, or if no header
+    /// was requested. A header with a field that didn't fit is returned as a
+    /// [`HeaderError`].
+    pub fn header(&self) -> Option<Result<GzHeader, HeaderError>> {
+        self.header.as_ref()?.parsed()
+    }
+
+    /// Resets the stream with `inflateReset` so it can decode a new message
+    /// with the same parameters.
+    ///
+    /// This discards any captured header.
+    pub fn reset(&mut self) -> Result<(), ZError> {
+        match unsafe { inflateReset(&mut *self.stream) } {
+            Z_OK => {
+                self.header = None;
+                Ok(())
+            }
+            ret => Err(self.error(ret)),
+        }
+    }
+
+    /// Resets the stream with `inflateReset2`, switching to new parameters.
+    ///
+    /// This discards any captured header.
+    pub fn reset2(&mut self, params: &InflateParams) -> Result<(), ZError> {
+        match unsafe { inflateReset2(&mut *self.stream, params.to_raw()) } {
+            Z_OK => {
+                self.header = None;
+                Ok(())
+            }
+            ret => Err(self.error(ret)),
+        }
+    }
+
+    /// Total number of bytes consumed so far.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn total_in(&self) -> u64 {
+        self.stream.total_in as u64
+    }
+
+    /// Total number of bytes produced so far.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn total_out(&self) -> u64 {
+        self.stream.total_out as u64
+    }
+
+    /// The underlying stream, for calling functions this type doesn't wrap.
+    pub fn as_raw(&mut self) -> z_streamp {
+        &mut *self.stream
+    }
+
+    fn error(&self, code: c_int) -> ZError {
+        unsafe { ZError::from_stream(code, &self.stream) }
+    }
+}
+
+impl Drop for Inflate {
+    fn drop(&mut self) {
+        unsafe {
+            inflateEnd(&mut *self.stream);
+        }
+    }
+}
+
+/// Points the stream at `input` and `output`, calls `f`, and reports how much
+/// of each was used.
+///
+/// Buffers larger than `uInt` can describe are only partially offered to
+/// zlib; the caller sees that as a short read or write.
+pub(crate) unsafe fn run(
+    stream: &mut z_stream,
+    input: &[u8],
+    output: &mut [u8],
+    f: impl FnOnce(z_streamp) -> c_int,
+) -> (c_int, usize, usize) {
+    let avail_in = input.len().min(c_uint::MAX as usize) as c_uint;
+    let avail_out = output.len().min(c_uint::MAX as usize) as c_uint;
+    stream.next_in = input.as_ptr() as *mut Bytef;
+    stream.avail_in = avail_in;
+    stream.next_out = output.as_mut_ptr();
+    stream.avail_out = avail_out;
+
+    let ret = f(stream);
+
+    let consumed = (avail_in - stream.avail_in) as usize;
+    let produced = (avail_out - stream.avail_out) as usize;
+    stream.next_in = ptr::null_mut();
+    stream.avail_in = 0;
+    stream.next_out = ptr::null_mut();
+    stream.avail_out = 0;
+    (ret, consumed, produced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::safe::{Adler32, ErrorCode, WindowBits};
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i % 251) as u8 ^ (i / 1024) as u8)
+            .collect()
+    }
+
+    /// Compresses `input` in full, offering the output `chunk` bytes at a
+    /// time.
+    fn deflate_all(stream: &mut Deflate, input: &[u8], chunk: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut buf = vec![0; chunk];
+        let mut input = input;
+        while !stream.is_finished() {
+            let (consumed, produced) = stream.compress(input, &mut buf, Flush::Finish).unwrap();
+            input = &input[consumed..];
+            out.extend_from_slice(&buf[..produced]);
+        }
+        out
+    }
+
+    fn inflate_all(stream: &mut Inflate, input: &[u8], chunk: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut buf = vec![0; chunk];
+        let mut input = input;
+        loop {
+            let (status, consumed, produced) =
+                stream.decompress(input, &mut buf, Flush::None).unwrap();
+            input = &input[consumed..];
+            out.extend_from_slice(&buf[..produced]);
+            match status {
+                Status::StreamEnd => return out,
+                Status::Ok => {}
+                status => panic!("unexpected {:?}", status),
+            }
+        }
+    }
+
+    #[test]
+    fn deflate_round_trip() {
+        let data = sample(100_000);
+        for &format in &[Format::Zlib, Format::Gzip, Format::Raw] {
+            for &level in &[Level::NONE, Level::FAST, Level::DEFAULT, Level::BEST] {
+                let mut deflate = Deflate::new(level, format).unwrap();
+                let compressed = deflate_all(&mut deflate, &data, 4096);
+                assert_eq!(deflate.total_in(), data.len() as u64);
+                assert_eq!(deflate.total_out(), compressed.len() as u64);
+
+                let mut inflate = Inflate::new(format).unwrap();
+                assert_eq!(inflate_all(&mut inflate, &compressed, 4096), data);
+            }
+        }
+    }
+
+    #[test]
+    fn deflate_tiny_output_buffer() {
+        let data = sample(10_000);
+        let mut deflate = Deflate::new(Level::DEFAULT, Format::Zlib).unwrap();
+        let compressed = deflate_all(&mut deflate, &data, 7);
+        let mut inflate = Inflate::new(Format::Zlib).unwrap();
+        assert_eq!(inflate_all(&mut inflate, &compressed, 4096), data);
+    }
+
+    #[test]
+    fn deflate_sync_flush_is_decodable() {
+        let mut deflate = Deflate::new(Level::DEFAULT, Format::Raw).unwrap();
+        let mut buf = vec![0; 1024];
+        let (consumed, produced) = deflate.compress(b"hello", &mut buf, Flush::Sync).unwrap();
+        assert_eq!(consumed, 5);
+        assert!(!deflate.is_finished());
+        // A sync flush ends on an empty stored block.
+        assert_eq!(&buf[produced - 4..produced], &[0, 0, 0xff, 0xff]);
+
+        let mut inflate = Inflate::new(Format::Raw).unwrap();
+        let mut out = vec![0; 16];
+        let (_, _, n) = inflate
+            .decompress(&buf[..produced], &mut out, Flush::Sync)
+            .unwrap();
+        assert_eq!(&out[..n], b"hello");
+    }
+
+    #[test]
+    fn deflate_reset_repeats_output() {
+        let data = sample(5_000);
+        let mut deflate = Deflate::new(Level::DEFAULT, Format::Gzip).unwrap();
+        let first = deflate_all(&mut deflate, &data, 1024);
+        deflate.reset().unwrap();
+        assert!(!deflate.is_finished());
+        assert_eq!(deflate.total_in(), 0);
+        assert_eq!(deflate_all(&mut deflate, &data, 1024), first);
+    }
+
+    #[test]
+    fn deflate_input_after_finish_is_an_error() {
+        let mut deflate = Deflate::new(Level::DEFAULT, Format::Zlib).unwrap();
+        deflate_all(&mut deflate, b"data", 64);
+        let mut buf = [0; 64];
+        let err = deflate
+            .compress(b"more", &mut buf, Flush::None)
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Stream);
+    }
+
+    #[test]
+    fn deflate_without_output_space_makes_no_progress() {
+        let mut deflate = Deflate::new(Level::DEFAULT, Format::Zlib).unwrap();
+        let (_, produced) = deflate.compress(b"data", &mut [], Flush::Finish).unwrap();
+        assert_eq!(produced, 0);
+        assert!(!deflate.is_finished());
+    }
+
+    #[test]
+    fn inflate_auto_detects_zlib_and_gzip() {
+        let data = sample(20_000);
+        for &format in &[Format::Zlib, Format::Gzip] {
+            let mut deflate = Deflate::new(Level::DEFAULT, format).unwrap();
+            let compressed = deflate_all(&mut deflate, &data, 4096);
+            let mut inflate = Inflate::new(Format::Auto).unwrap();
+            assert_eq!(inflate_all(&mut inflate, &compressed, 100), data);
+        }
+    }
+
+    #[test]
+    fn inflate_needs_dictionary() {
+        let dictionary = b"a preset dictionary with common words";
+        let data = b"common words from a preset dictionary";
+        let mut deflate = Deflate::new(Level::DEFAULT, Format::Zlib).unwrap();
+        let ret = unsafe {
+            deflateSetDictionary(
+                deflate.as_raw(),
+                dictionary.as_ptr(),
+                dictionary.len() as uInt,
+            )
+        };
+        assert_eq!(ret, Z_OK);
+        let compressed = deflate_all(&mut deflate, data, 1024);
+
+        let mut inflate = Inflate::new(Format::Zlib).unwrap();
+        let mut out = vec![0; 1024];
+        let (status, consumed, _) = inflate
+            .decompress(&compressed, &mut out, Flush::None)
+            .unwrap();
+        let mut adler = Adler32::new();
+        adler.update(dictionary);
+        assert_eq!(status, Status::NeedDict(adler.finalize()));
+
+        inflate.set_dictionary(dictionary).unwrap();
+        let (status, _, produced) = inflate
+            .decompress(&compressed[consumed..], &mut out, Flush::None)
+            .unwrap();
+        assert_eq!(status, Status::StreamEnd);
+        assert_eq!(&out[..produced], data);
+    }
+
+    #[test]
+    fn inflate_wrong_dictionary_is_an_error() {
+        let mut deflate = Deflate::new(Level::DEFAULT, Format::Zlib).unwrap();
+        let ret = unsafe { deflateSetDictionary(deflate.as_raw(), b"abc".as_ptr(), 3) };
+        assert_eq!(ret, Z_OK);
+        let compressed = deflate_all(&mut deflate, b"abcabc", 64);
+
+        let mut inflate = Inflate::new(Format::Zlib).unwrap();
+        let mut out = [0; 64];
+        let (status, _, _) = inflate
+            .decompress(&compressed, &mut out, Flush::None)
+            .unwrap();
+        assert!(matches!(status, Status::NeedDict(_)));
+        let err = inflate.set_dictionary(b"xyz").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Data);
+    }
+
+    #[test]
+    fn inflate_truncated_input_reports_buf_error() {
+        let data = sample(10_000);
+        let mut deflate = Deflate::new(Level::DEFAULT, Format::Zlib).unwrap();
+        let compressed = deflate_all(&mut deflate, &data, 4096);
+
+        let mut inflate = Inflate::new(Format::Zlib).unwrap();
+        let mut out = vec![0; 20_000];
+        let truncated = &compressed[..compressed.len() - 10];
+        let (status, consumed, _) = inflate
+            .decompress(truncated, &mut out, Flush::Finish)
+            .unwrap();
+        assert_eq!(consumed, truncated.len());
+        assert_eq!(status, Status::BufError);
+    }
+
+    #[test]
+    fn inflate_small_window_rejects_large_window_data() {
+        let data = sample(10_000);
+        let mut deflate = Deflate::new(Level::DEFAULT, Format::Zlib).unwrap();
+        let compressed = deflate_all(&mut deflate, &data, 4096);
+
+        let params = InflateParams::new().window_bits(WindowBits::new(9).unwrap());
+        let mut inflate = Inflate::with_params(&params).unwrap();
+        let mut out = vec![0; 20_000];
+        let err = inflate
+            .decompress(&compressed, &mut out, Flush::None)
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Data);
+    }
+
+    #[test]
+    fn inflate_reset_and_reset2() {
+        let data = sample(3_000);
+        let mut zlib = Deflate::new(Level::DEFAULT, Format::Zlib).unwrap();
+        let zlib_data = deflate_all(&mut zlib, &data, 4096);
+        let mut raw = Deflate::new(Level::DEFAULT, Format::Raw).unwrap();
+        let raw_data = deflate_all(&mut raw, &data, 4096);
+
+        let mut inflate = Inflate::new(Format::Zlib).unwrap();
+        assert_eq!(inflate_all(&mut inflate, &zlib_data, 512), data);
+        inflate.reset().unwrap();
+        assert_eq!(inflate.total_out(), 0);
+        assert_eq!(inflate_all(&mut inflate, &zlib_data, 512), data);
+        inflate
+            .reset2(&InflateParams::new().format(Format::Raw))
+            .unwrap();
+        assert_eq!(inflate_all(&mut inflate, &raw_data, 512), data);
+        assert_eq!(inflate.total_in(), raw_data.len() as u64);
+    }
+
+    #[cfg(zng)]
+    #[test]
+    fn set_params_applies_valid_params() {
+        use crate::safe::{DeflateParam, Level, Strategy};
+
+        let data = sample(50_000);
+        let mut deflate = Deflate::with_params(&DeflateParams::new()).unwrap();
+        deflate
+            .set_params(&[
+                DeflateParam::Level(Level::BEST),
+                DeflateParam::Strategy(Strategy::Filtered),
+                DeflateParam::Reproducible(true),
+            ])
+            .unwrap();
+        let compressed = deflate_all(&mut deflate, &data, 4096);
+        let mut inflate = Inflate::with_params(&InflateParams::new()).unwrap();
+        assert_eq!(inflate_all(&mut inflate, &compressed, 4096), data);
+    }
+
+    #[cfg(zng)]
+    #[test]
+    fn set_params_rejects_duplicates() {
+        use crate::safe::{DeflateParam, Level};
+
+        let mut deflate = Deflate::with_params(&DeflateParams::new()).unwrap();
+        let err = deflate
+            .set_params(&[
+                DeflateParam::Level(Level::FAST),
+                DeflateParam::Level(Level::BEST),
+            ])
+            .unwrap_err();
+        // zlib-ng flags the second occurrence.
+        assert_eq!(err.code(), ErrorCode::Buf);
+        assert!(err.to_string().contains("level 9 was rejected"), "{}", err);
+        // The stream is still usable.
+        let data = sample(1_000);
+        let compressed = deflate_all(&mut deflate, &data, 4096);
+        let mut inflate = Inflate::with_params(&InflateParams::new()).unwrap();
+        assert_eq!(inflate_all(&mut inflate, &compressed, 4096), data);
+    }
+}
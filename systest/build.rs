@@ -23,6 +23,13 @@ fn main() {
             if rust == "zlibVersion" {
                 return "zlibng_version".to_string();
             }
+            // zlib-ng has no separate `*64` variants; its functions always
+            // take 64-bit offsets.
+            if let Some(base) = rust.strip_suffix("64") {
+                if base.starts_with("gz") || base.contains("_combine") {
+                    return format!("zng_{}", base);
+                }
+            }
             if rust.starts_with("zng_") {
                 rust.to_string()
             } else {
@@ -30,6 +37,18 @@ fn main() {
             }
         });
         cfg.cfg("zng", None);
+    } else {
+        // systest depends on libz-sys with the `libc` feature, which enables
+        // the gz* and compress* bindings.
+        cfg.cfg("feature", Some("libc"));
+        // Declares the `*64` functions in zlib.h.
+        cfg.define("_LARGEFILE64_SOURCE", None);
+    }
+    if let Ok(cfgs) = env::var("DEP_Z_VERSION_CFGS") {
+        // Mirror the version gates that libz-sys enabled for itself.
+        for name in cfgs.split(',').filter(|s| !s.is_empty()) {
+            cfg.cfg(name, None);
+        }
     }
     cfg.type_name(move |n, _, _| {
         if zng {
@@ -41,7 +60,7 @@ fn main() {
                 return "zng_streamp".to_string();
             } else if n == "z_size" {
                 return "size_t".to_string();
-            } else if n == "z_checksum" {
+            } else if n == "z_checksum" || n == "z_crc_t" {
                 return "uint32_t".to_string();
             } else if n == "z_off_t" {
                 return "z_off64_t".to_string();
@@ -55,9 +74,14 @@ fn main() {
             n.to_string()
         }
     });
+    // The signedness of a C enum like `zng_deflate_param` is up to the
+    // compiler; only its size matters.
     cfg.skip_signededness(|ty| matches!(ty,
         "gz_headerp" | "voidpf" | "voidcf" | "voidp" | "out_func" | "voidpc" | "gzFile"
-        | "in_func" | "free_func" | "alloc_func" | "z_streamp"));
+        | "in_func" | "free_func" | "alloc_func" | "z_streamp" | "zng_deflate_param"));
+    // A Rust-side `Option<&str>` with the header's version string, which the
+    // header itself defines as a string literal.
+    cfg.skip_const(|n| n == "ZLIB_VERSION");
     cfg.skip_field_type(|s, field| s == "z_stream" && (field == "next_in" || field == "msg"));
     cfg.generate("../src/lib.rs", "all.rs");
 }
@@ -475,6 +475,10 @@ pub fn build_zlib_ng(target: &str, compat: bool) {
 
     if !compat {
         println!("cargo:rustc-cfg=zng");
+        // In compat mode the libz-sys build script reports `ZLIB_VERSION`
+        // instead.
+        println!("cargo:rustc-env=LIBZ_SYS_ZLIB_VERSION={}", version);
+        println!("cargo:version={}", version);
     }
 }
 
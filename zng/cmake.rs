@@ -71,6 +71,17 @@ pub fn build_zlib_ng(target: &str, compat: bool) {
     println!("cargo:include={}", includedir.to_str().unwrap());
     if !compat {
         println!("cargo:rustc-cfg=zng");
+        // In compat mode the libz-sys build script reports `ZLIB_VERSION`
+        // instead.
+        let header = std::fs::read_to_string(includedir.join("zlib-ng.h")).unwrap_or_default();
+        let version = header
+            .lines()
+            .find(|l| l.starts_with("#define ZLIBNG_VERSION "))
+            .and_then(|l| l.split('"').nth(1));
+        if let Some(version) = version {
+            println!("cargo:rustc-env=LIBZ_SYS_ZLIB_VERSION={}", version);
+            println!("cargo:version={}", version);
+        }
     }
 }
 